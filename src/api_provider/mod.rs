@@ -3,6 +3,7 @@ use anyhow::{Context, Result};
 use rand::{thread_rng, Rng};
 use std::time::SystemTime;
 
+#[derive(Debug)]
 pub struct WebEndpoint {
     url: String,
 }
@@ -43,7 +44,50 @@ impl HikAPI for WebEndpoint {
         format!("{}/ISAPI/Security/sessionHeartbeat", self.endpoint())
     }
 
+    fn logout_api(&self) -> String {
+        format!("{}/ISAPI/Security/sessionLogout", self.endpoint())
+    }
+
     fn online_users_api(&self) -> String {
         format!("{}/ISAPI/Security/onlineUser", self.endpoint())
     }
+
+    fn device_info_api(&self) -> String {
+        format!("{}/ISAPI/System/deviceInfo", self.endpoint())
+    }
+
+    fn channel_status_api(&self) -> String {
+        format!(
+            "{}/ISAPI/ContentMgmt/InputProxy/channels/status",
+            self.endpoint()
+        )
+    }
+
+    fn storage_api(&self) -> String {
+        format!("{}/ISAPI/ContentMgmt/Storage", self.endpoint())
+    }
+
+    fn picture_api(&self, channel: u32) -> String {
+        format!(
+            "{}/ISAPI/Streaming/channels/{}/picture",
+            self.endpoint(),
+            channel
+        )
+    }
+
+    fn alert_stream_api(&self) -> String {
+        format!("{}/ISAPI/Event/notification/alertStream", self.endpoint())
+    }
+
+    fn log_search_api(&self) -> String {
+        format!("{}/ISAPI/ContentMgmt/logSearch", self.endpoint())
+    }
+
+    fn ip_filter_api(&self) -> String {
+        format!("{}/ISAPI/System/Network/ipFilter", self.endpoint())
+    }
+
+    fn illegal_login_lock_api(&self) -> String {
+        format!("{}/ISAPI/Security/illegalLoginLock", self.endpoint())
+    }
 }
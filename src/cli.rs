@@ -0,0 +1,127 @@
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use std::{path::PathBuf, process::ExitCode};
+use tokio::time::error::Elapsed;
+
+use crate::{
+    client::ClientError,
+    export::{ExportFilter, ExportFormat},
+    probe::OnceFormat,
+};
+
+/// Watches who is logged into Hikvision devices.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file, Config.toml of the working directory or next to the
+    /// executable when left out.
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Only this device of the config.
+    #[arg(long, global = true)]
+    pub device: Option<String>,
+    /// Endpoint to use instead of the configured one, for a single device.
+    #[arg(long, global = true)]
+    pub endpoint: Option<String>,
+    /// Username to use instead of the configured one, for a single device.
+    #[arg(long, global = true)]
+    pub username: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Watch the devices in the terminal, the default.
+    Watch,
+    /// Print who is online now and exit.
+    Once(OnceArgs),
+    /// Log into the devices and exit, telling whether it worked.
+    Check,
+    /// Write the session history as CSV or JSON.
+    Export(ExportArgs),
+    /// Run without a terminal, writing events as JSON lines.
+    Daemon(DaemonArgs),
+}
+
+#[derive(Args)]
+pub struct OnceArgs {
+    /// table or json.
+    #[arg(long, default_value = "table")]
+    pub format: OnceFormat,
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// File to append events to, standard output when left out.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// csv or json.
+    #[arg(long, default_value = "csv")]
+    pub format: ExportFormat,
+    /// Sessions still online on or after this day, as YYYY-MM-DD.
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+    /// Sessions started on or before this day, as YYYY-MM-DD.
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+    /// Only this user, whatever the case.
+    #[arg(long)]
+    pub user: Option<String>,
+    /// Only sessions from this address.
+    #[arg(long)]
+    pub ip: Option<String>,
+    /// File to write, standard output when left out.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+impl ExportArgs {
+    pub fn filter(&self) -> ExportFilter {
+        ExportFilter {
+            from: self.from,
+            to: self.to,
+            user: self.user.clone(),
+            ip: self.ip.clone(),
+        }
+    }
+}
+
+/// What gusta exits with. Bad arguments exit with 2, as clap does, and the
+/// highest code wins when devices fail in different ways.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Exit {
+    Ok = 0,
+    /// Anything not covered below.
+    Failure = 1,
+    /// The config file is missing or invalid.
+    Config = 3,
+    /// A device rejected the credentials or locked the account.
+    Auth = 4,
+    /// A device did not answer or its certificate was not trusted.
+    Unreachable = 5,
+}
+
+impl Exit {
+    /// The code for a device that failed with `error`.
+    pub fn of(error: &anyhow::Error) -> Self {
+        if error.is::<Elapsed>() {
+            return Exit::Unreachable;
+        }
+        match error.downcast_ref::<ClientError>() {
+            Some(ClientError::Auth(_) | ClientError::Locked(_)) => Exit::Auth,
+            Some(ClientError::Network(_) | ClientError::Tls(_)) => Exit::Unreachable,
+            _ => Exit::Failure,
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
@@ -0,0 +1,95 @@
+use reqwest::Response;
+
+use crate::client::{ClientError, ClientResult, EventNotificationAlert};
+
+/// Reader over the never ending `multipart/mixed` answer of the alert stream.
+pub struct AlertStream {
+    res: Response,
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+struct Part {
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl AlertStream {
+    pub(super) fn new(res: Response) -> ClientResult<Self> {
+        let boundary = res
+            .headers()
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .and_then(utils::boundary)
+            .ok_or(ClientError::Parse("alert stream is not multipart".into()))?;
+
+        Ok(AlertStream {
+            res,
+            delimiter: format!("--{}", boundary).into_bytes(),
+            buf: vec![],
+        })
+    }
+
+    /// Next alert from the device, `None` once the device closed the stream.
+    pub async fn next(&mut self) -> ClientResult<Option<EventNotificationAlert>> {
+        loop {
+            while let Some(part) = self.take_part() {
+                let body = String::from_utf8_lossy(&part.body);
+                // pictures and anything else attached to an event are skipped
+                if part.content_type.contains("xml") && body.contains("<EventNotificationAlert") {
+                    return Ok(Some(serde_xml_rs::from_str(&body)?));
+                }
+            }
+
+            match self.res.chunk().await? {
+                Some(chunk) => self.buf.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn take_part(&mut self) -> Option<Part> {
+        let start = utils::find(&self.buf, &self.delimiter)? + self.delimiter.len();
+        let headers_end = start + utils::find(&self.buf[start..], b"\r\n\r\n")?;
+        let body_start = headers_end + 4;
+
+        let mut content_type = String::new();
+        let mut content_length = None;
+        for line in String::from_utf8_lossy(&self.buf[start..headers_end]).lines() {
+            match line.split_once(':') {
+                Some((name, value)) if name.trim().eq_ignore_ascii_case("Content-Type") => {
+                    content_type = value.trim().to_lowercase()
+                }
+                Some((name, value)) if name.trim().eq_ignore_ascii_case("Content-Length") => {
+                    content_length = value.trim().parse::<usize>().ok()
+                }
+                _ => {}
+            }
+        }
+
+        let body_end = match content_length {
+            Some(len) if self.buf.len() >= body_start + len => body_start + len,
+            Some(_) => return None,
+            None => body_start + utils::find(&self.buf[body_start..], &self.delimiter)?,
+        };
+
+        let body = self.buf[body_start..body_end].to_vec();
+        self.buf.drain(..body_end);
+
+        Some(Part { content_type, body })
+    }
+}
+
+mod utils {
+    pub fn boundary(content_type: &str) -> Option<String> {
+        content_type
+            .split(';')
+            .filter_map(|p| p.trim().split_once('='))
+            .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+            .map(|(_, value)| value.trim_matches('"').to_string())
+    }
+
+    pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+}
@@ -2,10 +2,12 @@ use anyhow::Error;
 use serde::{Deserialize, Serialize};
 use serde_xml_rs::from_str;
 
+use super::SecretString;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AuthSetting {
     #[serde(rename = "sessionID")]
-    pub session_id: String,
+    pub session_id: SecretString,
 
     pub challenge: String,
     pub iterations: u32,
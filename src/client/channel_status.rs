@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct InputProxyChannelStatusList {
+    #[serde(rename = "InputProxyChannelStatus", default)]
+    pub channels: Vec<InputProxyChannelStatus>,
+}
+
+/// State of one IP camera channel of a recorder.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InputProxyChannelStatus {
+    pub id: u32,
+
+    #[serde(rename = "sourceInputPortDescriptor")]
+    pub source: SourceInputPortDescriptor,
+
+    pub online: bool,
+
+    /// Why the channel is offline, e.g. `connect`, `netUnreachable`, `ipcStreamFail`.
+    #[serde(rename = "chanDetectResult", default)]
+    pub detect_result: String,
+
+    /// Not every firmware reports it.
+    #[serde(default)]
+    pub resolution: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SourceInputPortDescriptor {
+    #[serde(rename = "ipAddress", default)]
+    pub ip_address: String,
+
+    #[serde(rename = "managePortNo", default)]
+    pub manage_port: u16,
+}
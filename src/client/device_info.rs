@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeviceInfo {
+    #[serde(rename = "deviceName")]
+    pub device_name: String,
+
+    pub model: String,
+
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+
+    #[serde(rename = "macAddress")]
+    pub mac_address: String,
+
+    #[serde(rename = "firmwareVersion")]
+    pub firmware_version: String,
+
+    #[serde(rename = "firmwareReleasedDate", default)]
+    pub firmware_released_date: String,
+}
@@ -0,0 +1,115 @@
+use reqwest::StatusCode;
+use std::io;
+use thiserror::Error;
+
+use super::ResponseStatus;
+
+pub type ClientResult<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("unable to reach device: {0}")]
+    Network(reqwest::Error),
+
+    #[error("TLS handshake failed: {0}")]
+    Tls(reqwest::Error),
+
+    /// The device rejected our username or password.
+    #[error("wrong username or password")]
+    Auth(Option<ResponseStatus>),
+
+    /// Too many failed logins, the device refuses this account for a while.
+    #[error("account locked, unlocks in {}s", .0.unlock_time.unwrap_or_default())]
+    Locked(ResponseStatus),
+
+    /// The device no longer accepts our session.
+    #[error("session expired")]
+    SessionExpired,
+
+    /// The device has no `sessionLogin` endpoint.
+    #[error("session login is not supported")]
+    SessionLoginUnsupported,
+
+    /// Any other ISAPI error, with the `ResponseStatus` if the device sent one.
+    #[error("device responded with {status}{}", .response.as_ref().map(|r| format!(": {}", r)).unwrap_or_default())]
+    Device {
+        status: StatusCode,
+        response: Option<ResponseStatus>,
+    },
+
+    #[error("unable to parse response: {0}")]
+    Parse(String),
+
+    #[error("not logged in")]
+    NotConnected,
+
+    #[error("already connected")]
+    AlreadyConnected,
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ClientError {
+    /// Classifies a failed response from its HTTP status and `ResponseStatus` body.
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        match ResponseStatus::try_from(body).ok() {
+            Some(s) if s.is_locked() => ClientError::Locked(s),
+            Some(s) if s.is_bad_credentials() => ClientError::Auth(Some(s)),
+            None if status == StatusCode::UNAUTHORIZED => ClientError::Auth(None),
+            response => ClientError::Device { status, response },
+        }
+    }
+
+    /// The device does not have the endpoint, e.g. a camera asked about channels.
+    pub fn is_unsupported(&self) -> bool {
+        match self {
+            ClientError::Device { status, response } => {
+                *status == StatusCode::NOT_FOUND
+                    || response
+                        .as_ref()
+                        .is_some_and(|r| r.sub_status_code == "notSupport")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(value: reqwest::Error) -> Self {
+        if utils::is_tls_error(&value) {
+            ClientError::Tls(value)
+        } else {
+            ClientError::Network(value)
+        }
+    }
+}
+
+impl From<serde_xml_rs::Error> for ClientError {
+    fn from(value: serde_xml_rs::Error) -> Self {
+        ClientError::Parse(value.to_string())
+    }
+}
+
+mod utils {
+    use super::io;
+    use std::error::Error;
+
+    pub fn is_tls_error(err: &reqwest::Error) -> bool {
+        let mut source: Option<&(dyn Error + 'static)> = Some(err);
+        while let Some(e) = source {
+            if e.is::<rustls::Error>() {
+                return true;
+            }
+
+            // io errors hide the error they wrap from source(), and hyper
+            // wraps the one of rustls twice
+            source = match e.downcast_ref::<io::Error>().and_then(|io| io.get_ref()) {
+                Some(inner) => Some(inner as &(dyn Error + 'static)),
+                None => e.source(),
+            };
+        }
+
+        false
+    }
+}
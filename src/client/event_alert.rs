@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One event pushed by the device over the alert stream.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EventNotificationAlert {
+    #[serde(rename = "ipAddress", default)]
+    pub ip_address: String,
+
+    #[serde(rename = "channelID")]
+    pub channel_id: Option<u32>,
+
+    #[serde(rename = "dateTime")]
+    pub date_time: String,
+
+    /// How many times this event has been posted while it stayed active.
+    #[serde(rename = "activePostCount", default)]
+    pub active_post_count: u32,
+
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+
+    /// `active` or `inactive`.
+    #[serde(rename = "eventState")]
+    pub event_state: String,
+
+    #[serde(rename = "eventDescription", default)]
+    pub event_description: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AlertKind {
+    IllegalLogin,
+    Motion,
+    VideoLoss,
+    DiskError,
+    DiskFull,
+    Tampering,
+    Other(String),
+}
+
+impl EventNotificationAlert {
+    pub fn kind(&self) -> AlertKind {
+        match self.event_type.as_str() {
+            "illegalAccess" => AlertKind::IllegalLogin,
+            "VMD" => AlertKind::Motion,
+            "videoloss" => AlertKind::VideoLoss,
+            "diskerror" => AlertKind::DiskError,
+            "diskfull" => AlertKind::DiskFull,
+            "shelteralarm" | "tamperdetection" => AlertKind::Tampering,
+            other => AlertKind::Other(other.into()),
+        }
+    }
+
+    /// First post of an active event. Devices keep re-posting an event while
+    /// it lasts and send inactive `videoloss` as a keep-alive.
+    pub fn is_new(&self) -> bool {
+        self.event_state == "active" && self.active_post_count <= 1
+    }
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertKind::IllegalLogin => write!(f, "Illegal login"),
+            AlertKind::Motion => write!(f, "Motion"),
+            AlertKind::VideoLoss => write!(f, "Video loss"),
+            AlertKind::DiskError => write!(f, "HDD error"),
+            AlertKind::DiskFull => write!(f, "HDD full"),
+            AlertKind::Tampering => write!(f, "Tampering"),
+            AlertKind::Other(event_type) => write!(f, "{}", event_type),
+        }
+    }
+}
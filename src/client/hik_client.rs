@@ -1,218 +1,683 @@
-use crate::client::{OnlineUserList, SessionLogin};
-
-use super::auth_setting::*;
+use crate::client::{
+    AlertStream, AuthSetting, CMSearchResult, ClientError, ClientResult, DeviceInfo, IPFilter,
+    IllegalLoginLock, InputProxyChannelStatusList, LogEntry, LogQuery, OnlineUserList,
+    ResponseStatus, SecretString, SessionLogin, Storage, TlsOptions,
+};
 
 use anyhow::{Error, Result};
-use reqwest::Client;
+use digest_auth::WwwAuthenticateHeader;
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::Deserialize;
 use serde_xml_rs::to_string;
-use std::time::Duration;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::{
+    sync::watch,
     task::{self, JoinHandle},
     time,
 };
+use zeroize::Zeroizing;
 
 #[derive(Debug)]
 enum ClientStatus {
     NotConnected,
     Connected {
+        #[allow(dead_code)]
         auth_setting: AuthSetting,
         heart_beat_handle: JoinHandle<()>,
-        token: String,
+        token: SecretString,
     },
+    Digest {
+        prompt: Mutex<WwwAuthenticateHeader>,
+    },
+}
+
+/// How the client authenticates against the device.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Use `sessionLogin`, falling back to Digest when the device lacks it.
+    #[default]
+    Auto,
+    /// Cookie session from `sessionLogin` kept alive by heartbeats.
+    Session,
+    /// HTTP Digest auth on every request, for firmware without `sessionLogin`.
+    Digest,
+}
+
+/// Connection state reported to whoever holds a receiver from [`HikClient::state`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    SessionLost,
+    Reconnecting { attempt: u32, retry_in: Duration },
+    Locked { unlock_in: Duration },
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Disconnected => write!(f, "disconnected"),
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::SessionLost => write!(f, "session lost"),
+            ConnectionState::Reconnecting { attempt, retry_in } => write!(
+                f,
+                "login attempt {} failed, retrying in {}s",
+                attempt,
+                retry_in.as_secs()
+            ),
+            ConnectionState::Locked { unlock_in } => {
+                write!(f, "account locked, retrying in {}s", unlock_in.as_secs())
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct HikClient<T: HikAPI> {
     pub username: String,
-    pub password: String,
+    pub password: SecretString,
     pub api_provider: T,
+    auth_mode: AuthMode,
+    http: Client,
     connection: ClientStatus,
+    state: Arc<watch::Sender<ConnectionState>>,
+    hb_interval: Duration,
 }
 
 impl<T: HikAPI> HikClient<T> {
-    const HB_DELAY: u64 = 10;
+    // tests can't wait ten seconds to see a heartbeat, or its absence
+    const HB_DELAY: Duration = Duration::from_secs(10);
+    const HB_MAX_FAILURES: u32 = 3;
+    const RELOGIN_BASE_DELAY: Duration = Duration::from_secs(1);
+    const RELOGIN_MAX_DELAY: Duration = Duration::from_secs(60);
+    const LOG_SEARCH_PAGE: usize = 50;
 
     pub fn new(username: &str, password: &str, api_provider: T) -> Self {
+        let (state, _) = watch::channel(ConnectionState::Disconnected);
+
         HikClient {
             username: username.into(),
             password: password.into(),
             api_provider,
+            auth_mode: AuthMode::default(),
+            http: Client::new(),
             connection: ClientStatus::NotConnected,
+            state: Arc::new(state),
+            hb_interval: Self::HB_DELAY,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn logout(&mut self) {
-        self.disconnect();
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    /// Trusts the device certificate according to `tls`.
+    pub fn with_tls(mut self, tls: &TlsOptions) -> ClientResult<Self> {
+        self.http = tls.build_client()?;
+        Ok(self)
     }
 
-    pub async fn login(&mut self) -> Result<()> {
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.hb_interval = interval;
+    }
+
+    /// Stops the heartbeat and ends the session on the device, so it does
+    /// not count against its limit of sessions until it times out.
+    pub async fn logout(&mut self) {
+        if let Some(token) = self.disconnect() {
+            // the session times out anyway, failing to end it changes nothing
+            let _ = self.logout_request(&token).send().await;
+        }
+    }
+
+    pub async fn login(&mut self) -> ClientResult<()> {
         match self.connection {
             ClientStatus::NotConnected => {
-                let client = reqwest::Client::new();
-                let setting = self.fetch_auth_setting(&client).await?;
-
-                // login
-                let login_payload = SessionLogin {
-                    password: self.encoded_pwd(&setting)?,
-                    username: self.username.clone(),
-                    is_session_id_valid_long_term: setting.is_session_id_valid_long_term,
-                    session_id: setting.session_id.clone(),
-                    session_id_version: setting.session_id_version,
+                self.state.send_replace(ConnectionState::Connecting);
+                let result = match self.auth_mode {
+                    AuthMode::Session => self.open_session().await,
+                    AuthMode::Digest => self.open_digest().await,
+                    AuthMode::Auto => match self.open_session().await {
+                        Err(ClientError::SessionLoginUnsupported) => self.open_digest().await,
+                        res => res,
+                    },
                 };
+                if result.is_err() {
+                    self.connection = ClientStatus::NotConnected;
+                    self.state.send_replace(ConnectionState::Disconnected);
+                }
 
-                let payload_xml = to_string(&login_payload)?;
-                let login_res = client
-                    .post(self.api_provider.login_api()?)
-                    .body(payload_xml) // remove clone when done
-                    .send()
-                    .await?;
+                result
+            }
+            _ => Err(ClientError::AlreadyConnected),
+        }
+    }
 
-                let auth_token = match login_res.headers().get("Set-Cookie") {
-                    Some(t) => utils::extract_cookie(t.to_str()?)?,
-                    None => return Err(Error::msg("unable to login")),
-                };
+    async fn open_session(&mut self) -> ClientResult<()> {
+        let setting = self.fetch_auth_setting().await?;
 
-                let hb_handle = self.start_hb(&auth_token);
+        // login
+        let login_payload = SessionLogin {
+            password: self.encoded_pwd(&setting)?,
+            username: self.username.clone(),
+            is_session_id_valid_long_term: setting.is_session_id_valid_long_term,
+            session_id: setting.session_id.clone(),
+            session_id_version: setting.session_id_version,
+        };
 
-                self.connection = ClientStatus::Connected {
-                    auth_setting: setting,
-                    heart_beat_handle: hb_handle,
-                    token: auth_token,
-                };
-                Ok(())
+        let payload_xml = to_string(&login_payload)?;
+        let login_res = self
+            .http
+            .post(self.api_provider.login_api()?)
+            .body(payload_xml)
+            .send()
+            .await?;
+
+        let auth_token = match login_res.headers().get("Set-Cookie") {
+            Some(t) => utils::extract_cookie(t.to_str().map_err(anyhow::Error::from)?)?,
+            None => {
+                let status = login_res.status();
+                let body = login_res.text().await?;
+                return Err(ClientError::from_response(status, &body));
+            }
+        };
+
+        let hb_handle = self.start_hb(auth_token.clone());
+
+        self.connection = ClientStatus::Connected {
+            auth_setting: setting,
+            heart_beat_handle: hb_handle,
+            token: auth_token,
+        };
+        self.state.send_replace(ConnectionState::Connected);
+        Ok(())
+    }
+
+    async fn open_digest(&mut self) -> ClientResult<()> {
+        let api = self.api_provider.online_users_api();
+        let res = self.http.get(api.clone()).send().await?;
+
+        let prompt = match res.status() {
+            StatusCode::UNAUTHORIZED => utils::digest_prompt(&res)?,
+            status => {
+                return Err(ClientError::Device {
+                    status,
+                    response: None,
+                })
             }
+        };
+        self.connection = ClientStatus::Digest {
+            prompt: Mutex::new(prompt),
+        };
+
+        // the challenge alone says nothing about our credentials
+        let res = self.send(Method::GET, api, None).await?;
+        match utils::read_authed(res).await {
+            Err(ClientError::SessionExpired) => return Err(ClientError::Auth(None)),
+            res => res?,
+        };
+
+        self.state.send_replace(ConnectionState::Connected);
+        Ok(())
+    }
+
+    /// Sends a request authenticated the way the current connection expects.
+    async fn send(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<String>,
+    ) -> ClientResult<Response> {
+        let mut rq = self.http.request(method.clone(), url.clone());
+        if let Some(body) = &body {
+            rq = rq.body(body.clone());
+        }
+
+        match &self.connection {
+            ClientStatus::NotConnected => Err(ClientError::NotConnected),
             ClientStatus::Connected {
                 auth_setting: _,
                 heart_beat_handle: _,
-                token: _,
-            } => Err(Error::msg("already connected")),
+                token,
+            } => Ok(rq.header("Cookie", token).send().await?),
+            ClientStatus::Digest { prompt } => {
+                let authorization = self.digest_answer(prompt, &method, &url, &body)?;
+                let res = rq.header("Authorization", &authorization).send().await?;
+                if res.status() != StatusCode::UNAUTHORIZED {
+                    return Ok(res);
+                }
+
+                // the nonce went stale, answer the fresh challenge once
+                *prompt.lock().unwrap() = utils::digest_prompt(&res)?;
+                let authorization = self.digest_answer(prompt, &method, &url, &body)?;
+                let mut rq = self.http.request(method, url);
+                if let Some(body) = body {
+                    rq = rq.body(body);
+                }
+                Ok(rq.header("Authorization", &authorization).send().await?)
+            }
         }
     }
-    fn start_hb(&self, token: &str) -> JoinHandle<()> {
+
+    fn digest_answer(
+        &self,
+        prompt: &Mutex<WwwAuthenticateHeader>,
+        method: &Method,
+        url: &str,
+        body: &Option<String>,
+    ) -> Result<SecretString> {
+        let url = reqwest::Url::parse(url).map_err(anyhow::Error::from)?;
+        let uri = match url.query() {
+            Some(q) => format!("{}?{}", url.path(), q),
+            None => url.path().to_string(),
+        };
+        let context = digest_auth::AuthContext::new_with_method(
+            self.username.as_str(),
+            self.password.expose(),
+            uri,
+            body.as_ref().map(|b| b.as_bytes()),
+            method.as_str().into(),
+        );
+        let answer = prompt
+            .lock()
+            .unwrap()
+            .respond(&context)
+            .map_err(anyhow::Error::from)?;
+
+        Ok(answer.to_header_string().into())
+    }
+
+    /// Drops the current session and logs in again.
+    async fn reconnect(&mut self) -> ClientResult<()> {
+        self.disconnect();
+        self.connect().await
+    }
+
+    /// Logs in, backing off exponentially between failed attempts and waiting
+    /// out account locks. Gives up only when the credentials are rejected.
+    pub async fn connect(&mut self) -> ClientResult<()> {
+        let mut attempt = 0;
+        loop {
+            let retry_in = match self.login().await {
+                Ok(()) => return Ok(()),
+                // retrying would only get the account locked
+                Err(e @ ClientError::Auth(_)) => return Err(e),
+                Err(ClientError::Locked(status)) => {
+                    attempt = 0;
+                    let unlock_in = Duration::from_secs(status.unlock_time.unwrap_or(0).into())
+                        .max(Self::RELOGIN_BASE_DELAY);
+                    self.state
+                        .send_replace(ConnectionState::Locked { unlock_in });
+                    unlock_in
+                }
+                Err(_) => {
+                    attempt += 1;
+                    let retry_in = Self::relogin_delay(attempt);
+                    self.state
+                        .send_replace(ConnectionState::Reconnecting { attempt, retry_in });
+                    retry_in
+                }
+            };
+
+            time::sleep(retry_in).await;
+        }
+    }
+
+    /// The wait before the `attempt`th retry, doubling from a second up to a minute.
+    pub(crate) fn relogin_delay(attempt: u32) -> Duration {
+        Self::RELOGIN_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(Self::RELOGIN_MAX_DELAY)
+    }
+
+    fn start_hb(&self, token: SecretString) -> JoinHandle<()> {
         let hb_context = HeatbeatContext {
             api: self.api_provider.heartbeat_api(),
-            token: token.into(),
+            client: self.http.clone(),
+            token,
+            interval: self.hb_interval,
+            state: self.state.clone(),
         };
 
         let hb_handle: JoinHandle<()> = task::spawn(async move {
-            let mut interval = time::interval(Duration::from_secs(Self::HB_DELAY));
-            let HeatbeatContext { api, token } = hb_context;
-            let heart_beat_client = reqwest::Client::new();
+            let HeatbeatContext {
+                api,
+                client: heart_beat_client,
+                token,
+                interval,
+                state,
+            } = hb_context;
+            let mut interval = time::interval(interval);
+            let mut failures = 0;
 
             loop {
                 interval.tick().await;
 
                 let res = heart_beat_client
                     .put(api.clone())
-                    .header("Cookie", token.clone())
+                    .header("Cookie", &token)
                     .send()
                     .await;
 
-                match res {
-                    Ok(r) => match r.text().await {
-                        Ok(_) => {}
-                        Err(_) => {
-                            println!("unable to read hb response")
-                        }
+                let alive = match res {
+                    Ok(r) => match utils::read_authed(r).await {
+                        Ok(_) => true,
+                        Err(ClientError::SessionExpired) => break,
+                        Err(_) => false,
                     },
-                    Err(_) => println!("sending hb failed"),
+                    Err(_) => false,
+                };
+
+                if alive {
+                    failures = 0;
+                    continue;
+                }
+
+                failures += 1;
+                if failures >= Self::HB_MAX_FAILURES {
+                    break;
                 }
             }
+
+            state.send_replace(ConnectionState::SessionLost);
         });
 
         hb_handle
     }
 
     // ref script/lib/utils.js
-    async fn fetch_auth_setting(&self, rq_client: &Client) -> Result<AuthSetting> {
-        let res = rq_client
+    async fn fetch_auth_setting(&self) -> ClientResult<AuthSetting> {
+        let res = self
+            .http
             .get(self.api_provider.auth_setting_api(&self.username))
             .send()
             .await?;
+        if let StatusCode::NOT_FOUND | StatusCode::UNAUTHORIZED = res.status() {
+            return Err(ClientError::SessionLoginUnsupported);
+        }
         let xml = res.text().await?;
 
-        AuthSetting::try_from(xml.as_str())
+        AuthSetting::try_from(xml.as_str()).map_err(|e| ClientError::Parse(e.to_string()))
     }
 
-    pub async fn fetch_online_users(&self) -> Result<OnlineUserList> {
-        match &self.connection {
-            ClientStatus::NotConnected => Err(Error::msg("not logged in")),
-            ClientStatus::Connected {
-                auth_setting: _,
-                heart_beat_handle: _,
-                token,
-            } => {
-                let client = reqwest::Client::new();
-                let res = client
-                    .get(self.api_provider.online_users_api())
-                    .header("Cookie", token)
-                    .send()
-                    .await?;
-                let body = res.text().await?;
-                match serde_xml_rs::from_str::<OnlineUserList>(&body) {
-                    Ok(users) => Ok(users),
-                    Err(_) => Err(Error::msg("unable to deserialize result")),
-                }
+    pub async fn fetch_online_users(&mut self) -> ClientResult<OnlineUserList> {
+        let body = self
+            .request(Method::GET, self.api_provider.online_users_api(), None)
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn fetch_device_info(&mut self) -> ClientResult<DeviceInfo> {
+        let body = self
+            .request(Method::GET, self.api_provider.device_info_api(), None)
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn fetch_channel_status(&mut self) -> ClientResult<InputProxyChannelStatusList> {
+        let body = self
+            .request(Method::GET, self.api_provider.channel_status_api(), None)
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn fetch_storage(&mut self) -> ClientResult<Storage> {
+        let body = self
+            .request(Method::GET, self.api_provider.storage_api(), None)
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn fetch_ip_filter(&mut self) -> ClientResult<IPFilter> {
+        let body = self
+            .request(Method::GET, self.api_provider.ip_filter_api(), None)
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn update_ip_filter(&mut self, filter: &IPFilter) -> ClientResult<()> {
+        self.request(
+            Method::PUT,
+            self.api_provider.ip_filter_api(),
+            Some(filter.to_xml()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Adds `ip` to the device deny list, returns the filter as now stored.
+    pub async fn deny_ip(&mut self, ip: &str) -> ClientResult<IPFilter> {
+        let mut filter = self.fetch_ip_filter().await?;
+        if filter.deny(ip).map_err(Error::msg)? {
+            self.update_ip_filter(&filter).await?;
+        }
+
+        Ok(filter)
+    }
+
+    pub async fn remove_ip_filter(&mut self, id: u32) -> ClientResult<IPFilter> {
+        let mut filter = self.fetch_ip_filter().await?;
+        if filter.remove(id) {
+            self.update_ip_filter(&filter).await?;
+        }
+
+        Ok(filter)
+    }
+
+    pub async fn fetch_login_lock(&mut self) -> ClientResult<IllegalLoginLock> {
+        let body = self
+            .request(
+                Method::GET,
+                self.api_provider.illegal_login_lock_api(),
+                None,
+            )
+            .await?;
+
+        Ok(serde_xml_rs::from_str(&body)?)
+    }
+
+    pub async fn update_login_lock(&mut self, lock: &IllegalLoginLock) -> ClientResult<()> {
+        let payload_xml = to_string(lock)?;
+        self.request(
+            Method::PUT,
+            self.api_provider.illegal_login_lock_api(),
+            Some(payload_xml),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pages through the device log until every match was read.
+    pub async fn search_log(&mut self, query: &LogQuery) -> ClientResult<Vec<LogEntry>> {
+        let search_id = LogQuery::new_search_id();
+        let mut entries = vec![];
+        loop {
+            let description =
+                query.search_description(&search_id, entries.len(), Self::LOG_SEARCH_PAGE);
+            let body = self
+                .request(
+                    Method::POST,
+                    self.api_provider.log_search_api(),
+                    Some(description),
+                )
+                .await?;
+            let page: CMSearchResult = serde_xml_rs::from_str(&body)?;
+
+            let more = page.has_more() && !page.match_list.items.is_empty();
+            entries.extend(page.match_list.items.into_iter().map(|i| i.log));
+            if !more {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Opens the long-lived stream of device events. The stream outlives
+    /// neither the session nor the device rebooting; open it again once it ends.
+    pub async fn open_alert_stream(&mut self) -> ClientResult<AlertStream> {
+        let res = self
+            .request_unread(self.api_provider.alert_stream_api())
+            .await?;
+
+        AlertStream::new(res)
+    }
+
+    /// JPEG snapshot of a channel, `101` being the main stream of camera 1.
+    pub async fn fetch_picture(&mut self, channel: u32) -> ClientResult<Vec<u8>> {
+        let res = self
+            .request_unread(self.api_provider.picture_api(channel))
+            .await?;
+
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// GET whose successful answer is not text, handed back unread.
+    async fn request_unread(&mut self, url: String) -> ClientResult<Response> {
+        self.resume().await?;
+
+        let res = self.send(Method::GET, url.clone(), None).await?;
+        match utils::check_authed(res).await {
+            Err(ClientError::SessionExpired) => {
+                self.state.send_replace(ConnectionState::SessionLost);
+                self.reconnect().await?;
+
+                let res = self.send(Method::GET, url, None).await?;
+                utils::check_authed(res).await
             }
+            res => res,
         }
     }
 
-    fn encoded_pwd(&self, setting: &AuthSetting) -> Result<String> {
+    /// Fails when never logged in, logs in again if the session was lost.
+    async fn resume(&mut self) -> ClientResult<()> {
+        if let ClientStatus::NotConnected = self.connection {
+            return Err(ClientError::NotConnected);
+        }
+
+        if *self.state.borrow() == ConnectionState::SessionLost {
+            self.reconnect().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends an authenticated request and reads the answer, logging in again
+    /// first if the session was lost.
+    async fn request(
+        &mut self,
+        method: Method,
+        url: String,
+        body: Option<String>,
+    ) -> ClientResult<String> {
+        self.resume().await?;
+
+        let res = self.send(method.clone(), url.clone(), body.clone()).await?;
+        match utils::read_authed(res).await {
+            Err(ClientError::SessionExpired) => {
+                self.state.send_replace(ConnectionState::SessionLost);
+                self.reconnect().await?;
+
+                let res = self.send(method, url, body).await?;
+                utils::read_authed(res).await
+            }
+            res => res,
+        }
+    }
+
+    fn encoded_pwd(&self, setting: &AuthSetting) -> ClientResult<SecretString> {
+        // every step is as good as the password for this challenge
+        let digest = |s: &str| Zeroizing::new(sha256::digest(s));
+        let password = self.password.expose();
+
         if setting.is_irreversible {
-            let cred_hash = sha256::digest(
-                [
-                    self.username.clone(),
-                    setting.salt.clone(),
-                    self.password.clone(),
-                ]
-                .join(""),
-            );
-            let mut result = sha256::digest([cred_hash, setting.challenge.clone()].join(""));
+            let cred = Zeroizing::new([&self.username, &setting.salt, password].concat());
+            let cred_hash = digest(&cred);
+            let mut result = digest(&Zeroizing::new(
+                [cred_hash.as_str(), &setting.challenge].concat(),
+            ));
 
             for _ in 2..setting.iterations {
-                result = sha256::digest(result);
+                result = digest(&result);
             }
 
-            return Ok(result);
+            return Ok(result.as_str().into());
         }
 
-        let mut result = [
-            sha256::digest(self.password.clone()),
-            setting.challenge.clone(),
-        ]
-        .join("");
+        let mut result = Zeroizing::new([digest(password).as_str(), &setting.challenge].concat());
 
         for _ in 1..setting.iterations {
-            result = sha256::digest(result);
+            result = digest(&result);
         }
 
-        Ok(result)
+        Ok(result.as_str().into())
     }
 
-    fn disconnect(&mut self) {
-        match &self.connection {
-            ClientStatus::NotConnected => (),
+    fn logout_request(&self, token: &SecretString) -> reqwest::RequestBuilder {
+        self.http
+            .put(self.api_provider.logout_api())
+            .header("Cookie", token)
+    }
+
+    /// Stops the heartbeat, returning the token of the session if there
+    /// was one to end.
+    fn disconnect(&mut self) -> Option<SecretString> {
+        let token = match std::mem::replace(&mut self.connection, ClientStatus::NotConnected) {
+            ClientStatus::NotConnected => return None,
             ClientStatus::Connected {
                 auth_setting: _,
                 heart_beat_handle,
-                token: _,
+                token,
             } => {
                 heart_beat_handle.abort();
-                self.connection = ClientStatus::NotConnected
+                Some(token)
             }
-        }
+            ClientStatus::Digest { prompt: _ } => None,
+        };
+        self.state.send_replace(ConnectionState::Disconnected);
+
+        token
     }
 }
 
 struct HeatbeatContext {
     api: String,
-    token: String,
+    client: Client,
+    token: SecretString,
+    interval: Duration,
+    state: Arc<watch::Sender<ConnectionState>>,
 }
 
 impl<T: HikAPI> Drop for HikClient<T> {
     fn drop(&mut self) {
-        self.disconnect()
+        let Some(token) = self.disconnect() else {
+            return;
+        };
+        // a poller replaced on reload is dropped, not logged out; end its
+        // session in the background when there still is a runtime to do it
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let logout = self.logout_request(&token).send();
+            runtime.spawn(async move {
+                let _ = logout.await;
+            });
+        }
     }
 }
 
@@ -221,18 +686,68 @@ pub trait HikAPI {
     fn auth_setting_api(&self, username: &str) -> String;
     fn login_api(&self) -> Result<String>;
     fn heartbeat_api(&self) -> String;
+    fn logout_api(&self) -> String;
     fn online_users_api(&self) -> String;
+    fn device_info_api(&self) -> String;
+    fn channel_status_api(&self) -> String;
+    fn storage_api(&self) -> String;
+    fn picture_api(&self, channel: u32) -> String;
+    fn alert_stream_api(&self) -> String;
+    fn log_search_api(&self) -> String;
+    fn ip_filter_api(&self) -> String;
+    fn illegal_login_lock_api(&self) -> String;
 }
 
 mod utils {
+    use super::{ClientError, ClientResult, Response, ResponseStatus, SecretString, StatusCode};
     use anyhow::{Error, Result};
+    use digest_auth::WwwAuthenticateHeader;
 
-    pub fn extract_cookie(value: &str) -> Result<String> {
-        let splited: Vec<String> = value.split(';').map(|s| s.to_string()).collect();
-        let token = splited
-            .first()
+    pub fn extract_cookie(value: &str) -> Result<SecretString> {
+        let token = value
+            .split(';')
+            .next()
             .ok_or(Error::msg("unable to get auth token"))?;
 
-        Ok(token.clone())
+        Ok(token.into())
+    }
+
+    pub fn digest_prompt(res: &Response) -> Result<WwwAuthenticateHeader> {
+        let header = res
+            .headers()
+            .get("WWW-Authenticate")
+            .ok_or(Error::msg("missing digest challenge"))?;
+
+        Ok(digest_auth::parse(header.to_str()?)?)
+    }
+
+    /// Reads the body of an authenticated request, failing with
+    /// [`ClientError::SessionExpired`] when the device rejected our credentials.
+    pub async fn read_authed(res: Response) -> ClientResult<String> {
+        let status = res.status();
+        let body = res.text().await?;
+
+        let invalid_session = ResponseStatus::try_from(body.as_str())
+            .map(|s| s.is_invalid_session())
+            .unwrap_or(false);
+        if status == StatusCode::UNAUTHORIZED || invalid_session {
+            return Err(ClientError::SessionExpired);
+        }
+        if !status.is_success() {
+            return Err(ClientError::from_response(status, &body));
+        }
+
+        Ok(body)
+    }
+
+    /// Like [`read_authed`] but hands back a successful response unread, for
+    /// answers that are streamed or binary.
+    pub async fn check_authed(res: Response) -> ClientResult<Response> {
+        if res.status().is_success() {
+            return Ok(res);
+        }
+
+        read_authed(res).await?;
+        Err(ClientError::Other(Error::msg("unexpected answer")))
     }
 }
@@ -0,0 +1,24 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_xml_rs::from_str;
+
+/// Lock policy for accounts after repeated failed logins.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct IllegalLoginLock {
+    pub enabled: bool,
+
+    #[serde(rename = "maxIllegalLoginTimes")]
+    pub max_attempts: u32,
+
+    /// Minutes an account stays locked.
+    #[serde(rename = "lockDuration")]
+    pub lock_duration: u32,
+}
+
+impl TryFrom<&str> for IllegalLoginLock {
+    type Error = Error;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        Ok(from_str(value)?)
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+/// Device wide allow or deny list of client addresses.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct IPFilter {
+    pub enabled: bool,
+
+    /// `allow` or `deny`, applies to the whole list.
+    #[serde(rename = "permissionType")]
+    pub permission_type: String,
+
+    #[serde(rename = "IPFilterList", default)]
+    pub list: IPFilterList,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct IPFilterList {
+    #[serde(rename = "IPFilterAddress", default)]
+    pub addresses: Vec<IPFilterAddress>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct IPFilterAddress {
+    pub id: u32,
+
+    #[serde(
+        rename = "addressFilterType",
+        default = "IPFilterAddress::default_type"
+    )]
+    pub address_filter_type: String,
+
+    #[serde(rename = "AddressMask")]
+    pub address_mask: AddressMask,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AddressMask {
+    #[serde(rename = "ipAddress")]
+    pub ip_address: String,
+}
+
+impl IPFilter {
+    const DENY: &'static str = "deny";
+
+    /// Adds `ip` to the deny list, switching an empty filter to deny mode
+    /// and enabling it. Refuses to enable a filter still holding entries.
+    /// Returns false when it was already denied.
+    pub fn deny(&mut self, ip: &str) -> Result<bool, String> {
+        // enabling it would bring back every entry left in it as well
+        if !self.enabled && !self.list.addresses.is_empty() {
+            return Err(format!(
+                "IP filter is disabled but still holds {} entries, \
+                blocking would enable them too",
+                self.list.addresses.len()
+            ));
+        }
+        // an allow list would turn into a deny list of the very addresses it
+        // was meant to let in
+        if !self.list.addresses.is_empty() && self.permission_type != Self::DENY {
+            return Err(format!(
+                "IP filter is an {} list, blocking would lock everyone else out",
+                self.permission_type
+            ));
+        }
+
+        self.enabled = true;
+        self.permission_type = Self::DENY.into();
+        if self.denies(ip) {
+            return Ok(false);
+        }
+
+        let id = self.list.addresses.iter().map(|a| a.id).max().unwrap_or(0) + 1;
+        self.list.addresses.push(IPFilterAddress {
+            id,
+            address_filter_type: IPFilterAddress::default_type(),
+            address_mask: AddressMask {
+                ip_address: ip.into(),
+            },
+        });
+
+        Ok(true)
+    }
+
+    /// Returns false when there was no entry with that id.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.list.addresses.len();
+        self.list.addresses.retain(|a| a.id != id);
+
+        self.list.addresses.len() != before
+    }
+
+    pub fn denies(&self, ip: &str) -> bool {
+        self.enabled
+            && self.permission_type == Self::DENY
+            && self
+                .list
+                .addresses
+                .iter()
+                .any(|a| a.address_mask.ip_address == ip)
+    }
+
+    pub fn to_xml(&self) -> String {
+        let addresses = self
+            .list
+            .addresses
+            .iter()
+            .map(|a| {
+                format!(
+                    "<IPFilterAddress>\
+                    <id>{}</id>\
+                    <addressFilterType>{}</addressFilterType>\
+                    <AddressMask><ipAddress>{}</ipAddress></AddressMask>\
+                    </IPFilterAddress>",
+                    a.id, a.address_filter_type, a.address_mask.ip_address
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <IPFilter version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+            <enabled>{}</enabled>\
+            <permissionType>{}</permissionType>\
+            <IPFilterList>{}</IPFilterList>\
+            </IPFilter>",
+            self.enabled, self.permission_type, addresses
+        )
+    }
+}
+
+impl IPFilterAddress {
+    fn default_type() -> String {
+        "mask".into()
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::NaiveDateTime;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Minor types of the `Operation` log that show who got into the device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogMinorType {
+    RemoteLogin,
+    RemoteLogout,
+    IllegalLogin,
+}
+
+impl LogMinorType {
+    pub const ALL: [LogMinorType; 3] = [
+        LogMinorType::RemoteLogin,
+        LogMinorType::RemoteLogout,
+        LogMinorType::IllegalLogin,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            LogMinorType::RemoteLogin => "remoteLogin",
+            LogMinorType::RemoteLogout => "remoteLogout",
+            LogMinorType::IllegalLogin => "illegalLogin",
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match *self {
+            LogMinorType::RemoteLogin => "Remote login",
+            LogMinorType::RemoteLogout => "Remote logout",
+            LogMinorType::IllegalLogin => "Illegal login",
+        }
+    }
+}
+
+/// Operation log entries of the given minor types between `start` and `end`,
+/// both in device local time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogQuery {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub minor_types: Vec<LogMinorType>,
+}
+
+impl LogQuery {
+    const MAJOR_TYPE: &'static str = "Operation";
+    // no Z, which would claim UTC for a time in the device's zone
+    const TIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+    /// `CMSearchDescription` asking for one page of results.
+    pub fn search_description(&self, search_id: &str, position: usize, max: usize) -> String {
+        let descriptors = self
+            .minor_types
+            .iter()
+            .map(|t| {
+                format!(
+                    "<metadataDescriptor>//log.std-cgi.com/{}/{}</metadataDescriptor>",
+                    Self::MAJOR_TYPE,
+                    t.as_str()
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+            <CMSearchDescription version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+            <searchID>{}</searchID>\
+            <metaId>log.std-cgi.com</metaId>\
+            <timeSpanList><timeSpan>\
+            <startTime>{}</startTime>\
+            <endTime>{}</endTime>\
+            </timeSpan></timeSpanList>\
+            <maxResults>{}</maxResults>\
+            <searchResultPostion>{}</searchResultPostion>\
+            <metadataList>{}</metadataList>\
+            </CMSearchDescription>",
+            search_id,
+            self.start.format(Self::TIME_FORMAT),
+            self.end.format(Self::TIME_FORMAT),
+            max,
+            position,
+            descriptors
+        )
+    }
+
+    pub fn new_search_id() -> String {
+        let id = thread_rng().gen::<u128>();
+        let hex = format!("{:032X}", id);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CMSearchResult {
+    /// `OK`, `MORE` or `NO MATCHES`.
+    #[serde(rename = "responseStatusStrg")]
+    pub status: String,
+
+    #[serde(rename = "numOfMatches", default)]
+    pub num_of_matches: usize,
+
+    #[serde(rename = "matchList", default)]
+    pub match_list: MatchList,
+}
+
+impl CMSearchResult {
+    pub fn has_more(&self) -> bool {
+        self.status == "MORE"
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct MatchList {
+    #[serde(rename = "searchMatchItem", default)]
+    pub items: Vec<SearchMatchItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SearchMatchItem {
+    #[serde(rename = "logDescriptor")]
+    pub log: LogEntry,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LogEntry {
+    /// `log.std-cgi.com/<major type>/<minor type>`
+    #[serde(rename = "metaId")]
+    pub meta_id: String,
+
+    #[serde(rename = "StartDateTime")]
+    pub time: String,
+
+    #[serde(rename = "userName", default)]
+    pub user_name: String,
+
+    #[serde(rename = "ipAddress", default)]
+    pub ip_address: String,
+}
+
+impl LogEntry {
+    pub fn minor_type(&self) -> &str {
+        self.meta_id.rsplit('/').next().unwrap_or_default()
+    }
+}
@@ -0,0 +1,839 @@
+// in-process fake of the ISAPI endpoints HikClient talks to, used by tests
+use digest_auth::{AuthContext, AuthorizationHeader};
+use hyper::{
+    body, server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode,
+};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
+    time,
+};
+use tokio_rustls::{
+    rustls::{self, Certificate, PrivateKey},
+    TlsAcceptor,
+};
+
+use super::{
+    Hdd, IPFilter, IPFilterList, IllegalLoginLock, InputProxyChannelStatus, LogEntry, OnlineUser,
+    SessionLogin, SourceInputPortDescriptor,
+};
+
+pub const USERNAME: &str = "admin";
+pub const PASSWORD: &str = "12345abc";
+
+const SALT: &str = "8d8b0ab5c5f0b3c3d1f1";
+const CHALLENGE: &str = "e3a1c2f88a3b4b2d9a5a2c4f1e0d7b6a";
+const ITERATIONS: u32 = 100;
+const SESSION_ID: &str = "3b7fa8c1d2e94c6b";
+const LOGIN_ATTEMPTS: u32 = 5;
+
+/// Certificate served by [`MockDevice::start_tls`], signed by [`CA_FILE`].
+pub const SERVER_CERT: &[u8] = include_bytes!("testdata/server.der");
+const SERVER_KEY: &[u8] = include_bytes!("testdata/server.key.der");
+pub const CA_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/client/testdata/ca.pem");
+
+#[derive(Default)]
+struct DeviceState {
+    users: Vec<OnlineUser>,
+    delay: Duration,
+    offline: bool,
+    digest_only: bool,
+    nonce: u32,
+    failed_logins: u32,
+    unlock_time: u32,
+    tokens: HashSet<String>,
+    issued: u32,
+    logins: u32,
+    heartbeats: u32,
+    // tokens of the sessions logged out of, and heartbeats still sent with them
+    logged_out: HashSet<String>,
+    stale_heartbeats: u32,
+    alert_streams: Vec<UnboundedSender<String>>,
+    // alert streams refused, and how many were asked for
+    alerts_busy: bool,
+    alert_stream_requests: u32,
+    logs: Vec<LogEntry>,
+    ip_filter: Option<IPFilter>,
+    login_lock: Option<IllegalLoginLock>,
+    // None for a camera, which has no channels endpoint
+    channels: Option<Vec<InputProxyChannelStatus>>,
+    // None for a device without local storage
+    storage: Option<Vec<Hdd>>,
+    // channels answering snapshot requests
+    cameras: Vec<u32>,
+}
+
+pub struct MockDevice {
+    addr: SocketAddr,
+    tls: bool,
+    state: Arc<Mutex<DeviceState>>,
+    server_handle: JoinHandle<()>,
+}
+
+impl MockDevice {
+    pub fn start() -> Self {
+        Self::spawn(None)
+    }
+
+    /// Serves HTTPS with [`SERVER_CERT`].
+    pub fn start_tls() -> Self {
+        Self::spawn(Some(tls_acceptor()))
+    }
+
+    fn spawn(tls: Option<TlsAcceptor>) -> Self {
+        let state = Arc::new(Mutex::new(DeviceState::default()));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = TcpListener::from_std(listener).unwrap();
+
+        let is_tls = tls.is_some();
+        let svc_state = state.clone();
+        let server_handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = svc_state.clone();
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    let svc = service_fn(move |req| handle(state.clone(), req));
+                    let _ = match tls {
+                        Some(tls) => match tls.accept(stream).await {
+                            Ok(stream) => Http::new().serve_connection(stream, svc).await,
+                            Err(_) => return,
+                        },
+                        None => Http::new().serve_connection(stream, svc).await,
+                    };
+                });
+            }
+        });
+
+        MockDevice {
+            addr,
+            tls: is_tls,
+            state,
+            server_handle,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, self.addr)
+    }
+
+    pub fn set_users(&self, users: Vec<OnlineUser>) {
+        self.state.lock().unwrap().users = users;
+    }
+
+    /// Delays every response by `delay`.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Answers every request with 503, as a rebooting device would.
+    pub fn set_offline(&self, offline: bool) {
+        self.state.lock().unwrap().offline = offline;
+    }
+
+    /// Behaves like old firmware: no `sessionLogin`, Digest auth on every call.
+    pub fn set_digest_only(&self, digest_only: bool) {
+        self.state.lock().unwrap().digest_only = digest_only;
+    }
+
+    /// Issues a new Digest nonce, making answers to the old one stale.
+    pub fn rotate_nonce(&self) {
+        self.state.lock().unwrap().nonce += 1;
+    }
+
+    /// Locks the account for `unlock_time` seconds.
+    pub fn lock_account(&self, unlock_time: u32) {
+        self.state.lock().unwrap().unlock_time = unlock_time;
+    }
+
+    /// Drops every issued session, as a device reboot would.
+    pub fn expire_sessions(&self) {
+        self.state.lock().unwrap().tokens.clear();
+    }
+
+    pub fn set_channels(&self, channels: Vec<InputProxyChannelStatus>) {
+        self.state.lock().unwrap().channels = Some(channels);
+    }
+
+    pub fn set_storage(&self, hdds: Vec<Hdd>) {
+        self.state.lock().unwrap().storage = Some(hdds);
+    }
+
+    pub fn set_cameras(&self, channels: &[u32]) {
+        self.state.lock().unwrap().cameras = channels.to_vec();
+    }
+
+    pub fn set_logs(&self, logs: Vec<LogEntry>) {
+        self.state.lock().unwrap().logs = logs;
+    }
+
+    pub fn set_ip_filter(&self, filter: IPFilter) {
+        self.state.lock().unwrap().ip_filter = Some(filter);
+    }
+
+    pub fn ip_filter(&self) -> IPFilter {
+        self.state
+            .lock()
+            .unwrap()
+            .ip_filter
+            .clone()
+            .unwrap_or_else(no_ip_filter)
+    }
+
+    pub fn login_lock(&self) -> IllegalLoginLock {
+        self.state
+            .lock()
+            .unwrap()
+            .login_lock
+            .clone()
+            .unwrap_or_else(default_login_lock)
+    }
+
+    /// Refuses to open alert streams, as a device out of them does.
+    pub fn set_alerts_busy(&self, busy: bool) {
+        self.state.lock().unwrap().alerts_busy = busy;
+    }
+
+    pub fn alert_stream_requests(&self) -> u32 {
+        self.state.lock().unwrap().alert_stream_requests
+    }
+
+    /// Pushes an event to every open alert stream.
+    pub fn push_alert(&self, event_type: &str, event_state: &str, active_post_count: u32) {
+        let part = alert_part(event_type, event_state, active_post_count);
+        // split in two so readers have to put parts back together
+        let (head, tail) = part.split_at(part.len() / 2);
+        self.state
+            .lock()
+            .unwrap()
+            .alert_streams
+            .retain(|tx| tx.send(head.into()).is_ok() && tx.send(tail.into()).is_ok());
+    }
+
+    pub fn alert_streams(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.alert_streams.retain(|tx| !tx.is_closed());
+        state.alert_streams.len()
+    }
+
+    pub fn logins(&self) -> u32 {
+        self.state.lock().unwrap().logins
+    }
+
+    pub fn heartbeats(&self) -> u32 {
+        self.state.lock().unwrap().heartbeats
+    }
+
+    pub fn logouts(&self) -> usize {
+        self.state.lock().unwrap().logged_out.len()
+    }
+
+    /// Heartbeats of sessions that were logged out of.
+    pub fn stale_heartbeats(&self) -> u32 {
+        self.state.lock().unwrap().stale_heartbeats
+    }
+}
+
+impl Drop for MockDevice {
+    fn drop(&mut self) {
+        self.server_handle.abort();
+    }
+}
+
+/// Accepts TLS with [`SERVER_CERT`], for other servers tests stand up.
+pub fn tls_acceptor() -> TlsAcceptor {
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![Certificate(SERVER_CERT.to_vec())],
+            PrivateKey(SERVER_KEY.to_vec()),
+        )
+        .unwrap();
+
+    TlsAcceptor::from(Arc::new(config))
+}
+
+pub fn log(time: &str, minor_type: &str, user: &str, ip: &str) -> LogEntry {
+    LogEntry {
+        meta_id: format!("log.std-cgi.com/Operation/{minor_type}"),
+        time: time.into(),
+        user_name: user.into(),
+        ip_address: ip.into(),
+    }
+}
+
+pub fn channel(id: u32, ip: &str, online: bool) -> InputProxyChannelStatus {
+    InputProxyChannelStatus {
+        id,
+        source: SourceInputPortDescriptor {
+            ip_address: ip.into(),
+            manage_port: 8000,
+        },
+        online,
+        detect_result: if online { "connect" } else { "netUnreachable" }.into(),
+        resolution: "1920*1080".into(),
+    }
+}
+
+/// What looks enough like a JPEG, different for every channel.
+pub fn picture(channel: u32) -> Vec<u8> {
+    [&[0xff, 0xd8][..], &channel.to_be_bytes(), &[0xff, 0xd9]].concat()
+}
+
+/// A disk of `capacity` MB with `free_space` MB left.
+pub fn hdd(id: u32, status: &str, capacity: u64, free_space: u64) -> Hdd {
+    Hdd {
+        id,
+        name: String::new(),
+        status: status.into(),
+        capacity,
+        free_space,
+        property: "RW".into(),
+    }
+}
+
+pub fn user(id: u32, name: &str, ip: &str) -> OnlineUser {
+    OnlineUser {
+        id,
+        name: name.into(),
+        user_type: "operator".into(),
+        login_time: "2023-11-02T08:30:00+07:00".into(),
+        client_address: super::ClientAddress {
+            ip_address: ip.into(),
+        },
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<DeviceState>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let delay = state.lock().unwrap().delay;
+    if !delay.is_zero() {
+        time::sleep(delay).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = body::to_bytes(body).await.unwrap_or_default();
+    let body = String::from_utf8_lossy(&body);
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|c| c.to_str().ok())
+            .map(|c| c.to_string())
+    };
+    let cookie = header("Cookie");
+    let authorization = header("Authorization");
+
+    let mut state = state.lock().unwrap();
+    if state.offline {
+        return Ok(xml(StatusCode::SERVICE_UNAVAILABLE, String::new()));
+    }
+
+    let nonce = format!("{:032x}", state.nonce);
+    let logged_out = cookie
+        .as_ref()
+        .is_some_and(|c| state.logged_out.contains(c));
+    let authorized = if state.digest_only {
+        authorization.is_some_and(|a| digest_ok(&a, &nonce, &parts.method))
+    } else {
+        cookie.as_ref().is_some_and(|c| state.tokens.contains(c))
+    };
+
+    let picture_channel = parts
+        .uri
+        .path()
+        .strip_prefix("/ISAPI/Streaming/channels/")
+        .and_then(|p| p.strip_suffix("/picture"))
+        .and_then(|id| id.parse::<u32>().ok());
+
+    let mut res = match (parts.method, parts.uri.path()) {
+        (_, "/ISAPI/Security/sessionLogin/capabilities") | (_, "/ISAPI/Security/sessionLogin")
+            if state.digest_only =>
+        {
+            xml(
+                StatusCode::NOT_FOUND,
+                response_status(4, "Invalid Operation", "notSupport"),
+            )
+        }
+        (Method::GET, "/ISAPI/Security/sessionLogin/capabilities") => xml(
+            StatusCode::OK,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                <SessionLoginCap version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+                <sessionID>{SESSION_ID}</sessionID>\
+                <challenge>{CHALLENGE}</challenge>\
+                <iterations>{ITERATIONS}</iterations>\
+                <isSupportRTSPWithSession>true</isSupportRTSPWithSession>\
+                <isIrreversible>true</isIrreversible>\
+                <sessionIDVersion>2</sessionIDVersion>\
+                <salt>{SALT}</salt>\
+                <isSessionIDValidLongTerm>false</isSessionIDValidLongTerm>\
+                </SessionLoginCap>"
+            ),
+        ),
+        (Method::POST, "/ISAPI/Security/sessionLogin") => {
+            state.logins += 1;
+            match SessionLogin::try_from(body.as_ref()) {
+                _ if state.unlock_time > 0 => xml(
+                    StatusCode::UNAUTHORIZED,
+                    login_failed("lock", state.unlock_time, 0),
+                ),
+                Ok(login)
+                    if login.username == USERNAME && login.password.expose() == expected_pwd() =>
+                {
+                    state.failed_logins = 0;
+                    state.issued += 1;
+                    let token = format!("WebSession_{SESSION_ID}={:08x}", state.issued);
+                    state.tokens.insert(token.clone());
+
+                    let mut res = xml(StatusCode::OK, response_status(1, "OK", "ok"));
+                    res.headers_mut().insert(
+                        "Set-Cookie",
+                        format!("{token}; path=/;HttpOnly").parse().unwrap(),
+                    );
+                    res
+                }
+                _ => {
+                    state.failed_logins += 1;
+                    let retry = LOGIN_ATTEMPTS.saturating_sub(state.failed_logins);
+                    xml(StatusCode::UNAUTHORIZED, login_failed("unlock", 0, retry))
+                }
+            }
+        }
+        (Method::PUT, "/ISAPI/Security/sessionHeartbeat") if authorized => {
+            state.heartbeats += 1;
+            xml(StatusCode::OK, response_status(1, "OK", "ok"))
+        }
+        (Method::PUT, "/ISAPI/Security/sessionHeartbeat") if logged_out => {
+            state.stale_heartbeats += 1;
+            xml(
+                StatusCode::UNAUTHORIZED,
+                response_status(4, "Invalid Operation", "invalidSession"),
+            )
+        }
+        (Method::PUT, "/ISAPI/Security/sessionLogout") if authorized => {
+            if let Some(token) = cookie {
+                state.tokens.remove(&token);
+                state.logged_out.insert(token);
+            }
+            xml(StatusCode::OK, response_status(1, "OK", "ok"))
+        }
+        (Method::GET, "/ISAPI/Security/onlineUser") if authorized => {
+            xml(StatusCode::OK, online_user_list(&state.users))
+        }
+        (Method::GET, "/ISAPI/System/deviceInfo") if authorized => {
+            xml(StatusCode::OK, device_info())
+        }
+        (Method::GET, "/ISAPI/Event/notification/alertStream")
+            if authorized && state.alerts_busy =>
+        {
+            state.alert_stream_requests += 1;
+            xml(
+                StatusCode::SERVICE_UNAVAILABLE,
+                response_status(3, "Device Busy", "deviceBusy"),
+            )
+        }
+        (Method::GET, "/ISAPI/Event/notification/alertStream") if authorized => {
+            state.alert_stream_requests += 1;
+            let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+            let (mut body, stream) = Body::channel();
+            tokio::spawn(async move {
+                while let Some(part) = rx.recv().await {
+                    if body.send_data(part.into()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            state.alert_streams.push(tx);
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "multipart/mixed; boundary=boundary")
+                .body(stream)
+                .unwrap()
+        }
+        (Method::POST, "/ISAPI/ContentMgmt/logSearch") if authorized => {
+            xml(StatusCode::OK, log_search(&state.logs, &body))
+        }
+        (Method::GET, "/ISAPI/ContentMgmt/InputProxy/channels/status") if authorized => {
+            match &state.channels {
+                Some(channels) => xml(StatusCode::OK, channel_status_list(channels)),
+                None => xml(
+                    StatusCode::NOT_FOUND,
+                    response_status(4, "Invalid Operation", "notSupport"),
+                ),
+            }
+        }
+        (Method::GET, "/ISAPI/ContentMgmt/Storage") if authorized => match &state.storage {
+            Some(hdds) => xml(StatusCode::OK, storage(hdds)),
+            None => xml(
+                StatusCode::NOT_FOUND,
+                response_status(4, "Invalid Operation", "notSupport"),
+            ),
+        },
+        (Method::GET, _) if authorized && picture_channel.is_some() => {
+            match picture_channel.filter(|c| state.cameras.contains(c)) {
+                Some(channel) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "image/jpeg")
+                    .body(Body::from(picture(channel)))
+                    .unwrap(),
+                None => xml(
+                    StatusCode::NOT_FOUND,
+                    response_status(4, "Invalid Operation", "invalidID"),
+                ),
+            }
+        }
+        (Method::GET, "/ISAPI/System/Network/ipFilter") if authorized => {
+            let filter = state.ip_filter.clone().unwrap_or_else(no_ip_filter);
+            xml(StatusCode::OK, filter.to_xml())
+        }
+        (Method::PUT, "/ISAPI/System/Network/ipFilter") if authorized => {
+            match serde_xml_rs::from_str::<IPFilter>(&body) {
+                Ok(filter) => {
+                    state.ip_filter = Some(filter);
+                    xml(StatusCode::OK, response_status(1, "OK", "ok"))
+                }
+                Err(_) => xml(
+                    StatusCode::BAD_REQUEST,
+                    response_status(6, "Invalid XML Content", "badXmlContent"),
+                ),
+            }
+        }
+        (Method::GET, "/ISAPI/Security/illegalLoginLock") if authorized => {
+            let lock = state.login_lock.clone().unwrap_or_else(default_login_lock);
+            xml(StatusCode::OK, serde_xml_rs::to_string(&lock).unwrap())
+        }
+        (Method::PUT, "/ISAPI/Security/illegalLoginLock") if authorized => {
+            match IllegalLoginLock::try_from(body.as_ref()) {
+                Ok(lock) => {
+                    state.login_lock = Some(lock);
+                    xml(StatusCode::OK, response_status(1, "OK", "ok"))
+                }
+                Err(_) => xml(
+                    StatusCode::BAD_REQUEST,
+                    response_status(6, "Invalid XML Content", "badXmlContent"),
+                ),
+            }
+        }
+        (_, "/ISAPI/Security/sessionHeartbeat")
+        | (_, "/ISAPI/Security/sessionLogout")
+        | (_, "/ISAPI/ContentMgmt/InputProxy/channels/status")
+        | (_, "/ISAPI/ContentMgmt/Storage")
+        | (_, "/ISAPI/Security/illegalLoginLock")
+        | (_, "/ISAPI/System/Network/ipFilter")
+        | (_, "/ISAPI/Security/onlineUser")
+        | (_, "/ISAPI/ContentMgmt/logSearch")
+        | (_, "/ISAPI/System/deviceInfo")
+        | (_, "/ISAPI/Event/notification/alertStream") => xml(
+            StatusCode::UNAUTHORIZED,
+            response_status(4, "Invalid Operation", "invalidSession"),
+        ),
+        _ if picture_channel.is_some() => xml(
+            StatusCode::UNAUTHORIZED,
+            response_status(4, "Invalid Operation", "invalidSession"),
+        ),
+        _ => xml(
+            StatusCode::NOT_FOUND,
+            response_status(4, "Invalid Operation", "notSupport"),
+        ),
+    };
+
+    if state.digest_only && res.status() == StatusCode::UNAUTHORIZED {
+        let challenge = format!(
+            "Digest qop=\"auth\", realm=\"IP Camera(C6214)\", nonce=\"{nonce}\", stale=\"FALSE\""
+        );
+        res.headers_mut()
+            .insert("WWW-Authenticate", challenge.parse().unwrap());
+    }
+
+    Ok(res)
+}
+
+fn digest_ok(authorization: &str, nonce: &str, method: &Method) -> bool {
+    let answer = match AuthorizationHeader::parse(authorization) {
+        Ok(a) if a.nonce == nonce && a.username == USERNAME => a,
+        _ => return false,
+    };
+
+    let context = AuthContext::new_with_method(
+        USERNAME,
+        PASSWORD,
+        answer.uri.as_str(),
+        Option::<&[u8]>::None,
+        method.as_str().into(),
+    );
+    let mut expected = answer.clone();
+    expected.digest(&context);
+
+    expected.response == answer.response
+}
+
+// independent take on the device side of script/lib/utils.js
+fn expected_pwd() -> String {
+    let cred_hash = sha256::digest(format!("{USERNAME}{SALT}{PASSWORD}"));
+    let mut result = sha256::digest(format!("{cred_hash}{CHALLENGE}"));
+    for _ in 2..ITERATIONS {
+        result = sha256::digest(result);
+    }
+
+    result
+}
+
+fn xml(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/xml; charset=\"UTF-8\"")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn response_status(code: u32, status: &str, sub_status: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <ResponseStatus version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        <requestURL></requestURL>\
+        <statusCode>{code}</statusCode>\
+        <statusString>{status}</statusString>\
+        <subStatusCode>{sub_status}</subStatusCode>\
+        </ResponseStatus>"
+    )
+}
+
+fn login_failed(lock_status: &str, unlock_time: u32, retry_login_time: u32) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <ResponseStatus version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        <requestURL>/ISAPI/Security/sessionLogin</requestURL>\
+        <statusCode>4</statusCode>\
+        <statusString>Invalid Operation</statusString>\
+        <subStatusCode>invalidUserNameOrPasswd</subStatusCode>\
+        <lockStatus>{lock_status}</lockStatus>\
+        <unlockTime>{unlock_time}</unlockTime>\
+        <retryLoginTime>{retry_login_time}</retryLoginTime>\
+        </ResponseStatus>"
+    )
+}
+
+fn device_info() -> String {
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+    <DeviceInfo version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+    <deviceName>Front gate NVR</deviceName>\
+    <deviceID>48443730-3835-3336-3437-4419b6a1c2d3</deviceID>\
+    <model>DS-7608NI-K2</model>\
+    <serialNumber>DS-7608NI-K20820200916CCRRF12345678WCVU</serialNumber>\
+    <macAddress>44:19:b6:a1:c2:d3</macAddress>\
+    <firmwareVersion>V4.30.085</firmwareVersion>\
+    <firmwareReleasedDate>build 200916</firmwareReleasedDate>\
+    <deviceType>NVR</deviceType>\
+    </DeviceInfo>"
+        .into()
+}
+
+fn alert_part(event_type: &str, event_state: &str, active_post_count: u32) -> String {
+    let alert = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <EventNotificationAlert version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        <ipAddress>10.0.0.2</ipAddress>\
+        <portNo>80</portNo>\
+        <protocol>HTTP</protocol>\
+        <macAddress>44:19:b6:a1:c2:d3</macAddress>\
+        <channelID>1</channelID>\
+        <dateTime>2023-11-02T21:04:11+07:00</dateTime>\
+        <activePostCount>{active_post_count}</activePostCount>\
+        <eventType>{event_type}</eventType>\
+        <eventState>{event_state}</eventState>\
+        <eventDescription>{event_type} alarm</eventDescription>\
+        </EventNotificationAlert>"
+    );
+
+    format!(
+        "--boundary\r\n\
+        Content-Type: application/xml; charset=\"UTF-8\"\r\n\
+        Content-Length: {}\r\n\
+        \r\n\
+        {alert}\r\n",
+        alert.len()
+    )
+}
+
+fn default_login_lock() -> IllegalLoginLock {
+    IllegalLoginLock {
+        enabled: true,
+        max_attempts: LOGIN_ATTEMPTS,
+        lock_duration: 30,
+    }
+}
+
+// what a device that never had its IP filter touched answers
+fn no_ip_filter() -> IPFilter {
+    IPFilter {
+        enabled: false,
+        permission_type: "deny".into(),
+        list: IPFilterList::default(),
+    }
+}
+
+fn log_search(logs: &[LogEntry], description: &str) -> String {
+    let tags = |name: &str| {
+        description
+            .split(&format!("<{name}>"))
+            .skip(1)
+            .filter_map(|s| {
+                s.split_once(&format!("</{name}>"))
+                    .map(|(v, _)| v.to_string())
+            })
+            .collect::<Vec<String>>()
+    };
+    let start = tags("startTime").concat();
+    let end = tags("endTime").concat();
+    let position = tags("searchResultPostion").concat().parse().unwrap_or(0);
+    let max = tags("maxResults").concat().parse().unwrap_or(0);
+    let minor_types = tags("metadataDescriptor")
+        .iter()
+        .filter_map(|d| d.rsplit('/').next().map(|t| t.to_string()))
+        .collect::<Vec<String>>();
+
+    let matches = logs
+        .iter()
+        .filter(|l| start <= l.time && l.time <= end)
+        .filter(|l| minor_types.iter().any(|t| t == l.minor_type()))
+        .collect::<Vec<&LogEntry>>();
+    let page = matches
+        .iter()
+        .skip(position)
+        .take(max)
+        .map(|l| {
+            format!(
+                "<searchMatchItem><logDescriptor>\
+                <metaId>{}</metaId>\
+                <StartDateTime>{}</StartDateTime>\
+                <userName>{}</userName>\
+                <ipAddress>{}</ipAddress>\
+                </logDescriptor></searchMatchItem>",
+                l.meta_id, l.time, l.user_name, l.ip_address
+            )
+        })
+        .collect::<Vec<String>>();
+    let status = match matches.len() {
+        0 => "NO MATCHES",
+        n if position + page.len() < n => "MORE",
+        _ => "OK",
+    };
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <CMSearchResult version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        <searchID>{}</searchID>\
+        <responseStatus>true</responseStatus>\
+        <responseStatusStrg>{status}</responseStatusStrg>\
+        <numOfMatches>{}</numOfMatches>\
+        <matchList>{}</matchList>\
+        </CMSearchResult>",
+        tags("searchID").concat(),
+        page.len(),
+        page.concat()
+    )
+}
+
+fn channel_status_list(channels: &[InputProxyChannelStatus]) -> String {
+    let channels = channels
+        .iter()
+        .map(|c| {
+            format!(
+                "<InputProxyChannelStatus>\
+                <id>{}</id>\
+                <sourceInputPortDescriptor>\
+                <proxyProtocol>HIKVISION</proxyProtocol>\
+                <addressingFormatType>ipaddress</addressingFormatType>\
+                <ipAddress>{}</ipAddress>\
+                <managePortNo>{}</managePortNo>\
+                </sourceInputPortDescriptor>\
+                <online>{}</online>\
+                <chanDetectResult>{}</chanDetectResult>\
+                <resolution>{}</resolution>\
+                </InputProxyChannelStatus>",
+                c.id,
+                c.source.ip_address,
+                c.source.manage_port,
+                c.online,
+                c.detect_result,
+                c.resolution
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <InputProxyChannelStatusList version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        {channels}\
+        </InputProxyChannelStatusList>"
+    )
+}
+
+fn storage(hdds: &[Hdd]) -> String {
+    let hdds = hdds
+        .iter()
+        .map(|h| {
+            format!(
+                "<hdd>\
+                <id>{}</id>\
+                <hddName>{}</hddName>\
+                <hddPath></hddPath>\
+                <hddType>SATA</hddType>\
+                <status>{}</status>\
+                <capacity>{}</capacity>\
+                <freeSpace>{}</freeSpace>\
+                <property>{}</property>\
+                </hdd>",
+                h.id, h.name, h.status, h.capacity, h.free_space, h.property
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <storage version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        <hddList>{hdds}</hddList>\
+        </storage>"
+    )
+}
+
+fn online_user_list(users: &[OnlineUser]) -> String {
+    let users = users
+        .iter()
+        .map(|u| {
+            format!(
+                "<OnlineUser>\
+                <id>{}</id>\
+                <name>{}</name>\
+                <type>{}</type>\
+                <loginTime>{}</loginTime>\
+                <clientAddress><ipAddress>{}</ipAddress></clientAddress>\
+                </OnlineUser>",
+                u.id, u.name, u.user_type, u.login_time, u.client_address.ip_address
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <OnlineUserList version=\"2.0\" xmlns=\"http://www.isapi.org/ver20/XMLSchema\">\
+        {users}\
+        </OnlineUserList>"
+    )
+}
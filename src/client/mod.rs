@@ -1,9 +1,38 @@
+pub use alert_stream::*;
 pub use auth_setting::*;
+pub use channel_status::*;
+pub use device_info::*;
+pub use error::*;
+pub use event_alert::*;
 pub use hik_client::*;
+pub use illegal_login_lock::*;
+pub use ip_filter::*;
+pub use log_search::*;
 pub use online_user::*;
+pub use response_status::*;
+pub use secret_string::*;
 pub use session_login::*;
+pub use storage::*;
+pub use tls::*;
 
+mod alert_stream;
 mod auth_setting;
+mod channel_status;
+mod device_info;
+mod error;
+mod event_alert;
 mod hik_client;
+mod illegal_login_lock;
+mod ip_filter;
+mod log_search;
 mod online_user;
+mod response_status;
+mod secret_string;
 mod session_login;
+mod storage;
+mod tls;
+
+#[cfg(test)]
+pub mod mock_device;
+#[cfg(test)]
+mod tests;
@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct OnlineUserList {
-    #[serde(rename = "OnlineUser")]
+    #[serde(rename = "OnlineUser", default)]
     pub users: Vec<OnlineUser>,
 }
 
@@ -0,0 +1,62 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_xml_rs::from_str;
+use std::fmt;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResponseStatus {
+    #[serde(rename = "statusCode")]
+    pub status_code: u32,
+
+    #[serde(rename = "statusString", default)]
+    pub status_string: String,
+
+    #[serde(rename = "subStatusCode")]
+    pub sub_status_code: String,
+
+    /// `lock` or `unlock`, sent back on failed logins.
+    #[serde(rename = "lockStatus")]
+    pub lock_status: Option<String>,
+
+    /// Seconds until a locked account can log in again.
+    #[serde(rename = "unlockTime")]
+    pub unlock_time: Option<u32>,
+
+    /// Failed logins left before the account gets locked.
+    #[serde(rename = "retryLoginTime")]
+    pub retry_login_time: Option<u32>,
+}
+
+impl ResponseStatus {
+    // sub status codes devices answer with once our session is gone
+    const INVALID_SESSION: [&'static str; 3] =
+        ["invalidSession", "sessionExpired", "badAuthorization"];
+
+    const BAD_CREDENTIALS: [&'static str; 2] = ["invalidUserNameOrPasswd", "userNameOrPasswdError"];
+
+    pub fn is_invalid_session(&self) -> bool {
+        Self::INVALID_SESSION.contains(&self.sub_status_code.as_str())
+    }
+
+    pub fn is_bad_credentials(&self) -> bool {
+        Self::BAD_CREDENTIALS.contains(&self.sub_status_code.as_str())
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock_status.as_deref() == Some("lock") || self.sub_status_code == "userLocked"
+    }
+}
+
+impl fmt::Display for ResponseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.status_string, self.sub_status_code)
+    }
+}
+
+impl TryFrom<&str> for ResponseStatus {
+    type Error = Error;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        Ok(from_str(value)?)
+    }
+}
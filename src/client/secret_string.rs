@@ -0,0 +1,58 @@
+use reqwest::header::{HeaderValue, InvalidHeaderValue};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// A password, token or the like. Wiped from memory when dropped and
+/// printed as `[redacted]` by `Debug` and `Display`.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// The secret itself, to be handed over without keeping a copy.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        SecretString(value.into())
+    }
+}
+
+/// Marked sensitive, so reqwest leaves it out of its own `Debug` output.
+impl TryFrom<&SecretString> for HeaderValue {
+    type Error = InvalidHeaderValue;
+
+    fn try_from(value: &SecretString) -> Result<Self, Self::Error> {
+        let mut header = HeaderValue::from_str(value.expose())?;
+        header.set_sensitive(true);
+        Ok(header)
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
@@ -2,15 +2,17 @@ use anyhow::Error;
 use serde::{Deserialize, Serialize};
 use serde_xml_rs::from_str;
 
+use super::SecretString;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SessionLogin {
     #[serde(rename = "userName")]
     pub username: String,
 
-    pub password: String,
+    pub password: SecretString,
 
     #[serde(rename = "sessionID")]
-    pub session_id: String,
+    pub session_id: SecretString,
 
     #[serde(rename = "isSessionIDValidLongTerm")]
     pub is_session_id_valid_long_term: bool,
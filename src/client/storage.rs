@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Storage {
+    #[serde(rename = "hddList", default)]
+    pub hdd_list: HddList,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct HddList {
+    #[serde(rename = "hdd", default)]
+    pub hdds: Vec<Hdd>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Hdd {
+    pub id: u32,
+
+    #[serde(rename = "hddName", default)]
+    pub name: String,
+
+    /// `ok`, `unformatted`, `error`, `smartFailed`, `mismatch`, `offline`, ...
+    pub status: String,
+
+    /// Megabytes.
+    pub capacity: u64,
+
+    /// Megabytes.
+    #[serde(rename = "freeSpace")]
+    pub free_space: u64,
+
+    /// `RW`, `RO` or `Redund`.
+    #[serde(default)]
+    pub property: String,
+}
+
+impl Hdd {
+    pub fn is_ok(&self) -> bool {
+        self.status == "ok"
+    }
+
+    pub fn free_percent(&self) -> u8 {
+        match self.capacity {
+            0 => 0,
+            capacity => (self.free_space.min(capacity) * 100 / capacity) as u8,
+        }
+    }
+}
@@ -0,0 +1,696 @@
+use std::time::{Duration, Instant};
+
+use tokio::time;
+
+use super::{
+    mock_device::{self, MockDevice, CA_FILE, PASSWORD, SERVER_CERT, USERNAME},
+    AlertKind, AuthMode, ClientError, ConnectionState, HikClient, IPFilter, IllegalLoginLock,
+    LogMinorType, LogQuery, SessionLogin, TlsOptions,
+};
+use crate::{api_provider::WebEndpoint, testing::day};
+
+fn client(device: &MockDevice, password: &str) -> HikClient<WebEndpoint> {
+    HikClient::new(USERNAME, password, WebEndpoint::new(&device.endpoint()))
+}
+
+async fn wait_for(mut cond: impl FnMut() -> bool) -> bool {
+    for _ in 0..50 {
+        if cond() {
+            return true;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    false
+}
+
+#[tokio::test]
+async fn login_succeeds() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+
+    client.login().await.unwrap();
+
+    assert_eq!(device.logins(), 1);
+}
+
+#[tokio::test]
+async fn login_twice_fails() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+
+    client.login().await.unwrap();
+
+    assert!(client.login().await.is_err());
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_fails() {
+    let device = MockDevice::start();
+    let mut client = client(&device, "not-the-password");
+
+    match client.login().await {
+        Err(ClientError::Auth(Some(status))) => assert_eq!(status.retry_login_time, Some(4)),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert!(matches!(
+        client.fetch_online_users().await,
+        Err(ClientError::NotConnected)
+    ));
+}
+
+#[tokio::test]
+async fn login_on_locked_account_reports_unlock_time() {
+    let device = MockDevice::start();
+    device.lock_account(1800);
+    let mut client = client(&device, PASSWORD);
+
+    match client.login().await {
+        Err(ClientError::Locked(status)) => assert_eq!(status.unlock_time, Some(1800)),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn connect_gives_up_on_wrong_password() {
+    let device = MockDevice::start();
+    let mut client = client(&device, "not-the-password");
+
+    assert!(matches!(client.connect().await, Err(ClientError::Auth(_))));
+    assert_eq!(device.logins(), 1);
+}
+
+#[tokio::test]
+async fn connect_waits_for_account_unlock() {
+    let device = MockDevice::start();
+    device.lock_account(1);
+    let mut client = client(&device, PASSWORD);
+    let state = client.state();
+    let connect = tokio::spawn(async move {
+        let res = client.connect().await;
+        (client, res)
+    });
+
+    assert!(wait_for(|| matches!(*state.borrow(), ConnectionState::Locked { .. })).await);
+    device.lock_account(0);
+
+    let (_client, res) = connect.await.unwrap();
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn unreachable_device_is_a_network_error() {
+    let mut client = HikClient::new(USERNAME, PASSWORD, WebEndpoint::new("http://127.0.0.1:1"));
+
+    assert!(matches!(client.login().await, Err(ClientError::Network(_))));
+}
+
+#[tokio::test]
+async fn heartbeat_starts_after_login() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+
+    client.login().await.unwrap();
+
+    assert!(wait_for(|| device.heartbeats() > 0).await);
+}
+
+#[tokio::test]
+async fn heartbeat_stops_after_logout() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+
+    client.login().await.unwrap();
+    assert!(wait_for(|| device.heartbeats() > 0).await);
+    client.logout().await;
+    assert_eq!(device.logouts(), 1);
+
+    let sent = device.heartbeats();
+    time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(device.heartbeats(), sent);
+}
+
+#[tokio::test]
+async fn fetch_online_users_returns_users() {
+    let device = MockDevice::start();
+    device.set_users(vec![
+        mock_device::user(1, USERNAME, "10.0.0.5"),
+        mock_device::user(2, "guard", "10.0.0.77"),
+    ]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert_eq!(online.users.len(), 2);
+    assert_eq!(online.users[1].name, "guard");
+    assert_eq!(online.users[1].client_address.ip_address, "10.0.0.77");
+}
+
+#[tokio::test]
+async fn fetch_online_users_handles_empty_list() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert!(online.users.is_empty());
+}
+
+#[tokio::test]
+async fn fetch_online_users_before_login_fails() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+
+    assert!(client.fetch_online_users().await.is_err());
+}
+
+#[tokio::test]
+async fn fetch_online_users_relogins_after_cookie_expired() {
+    let device = MockDevice::start();
+    device.set_users(vec![mock_device::user(1, USERNAME, "10.0.0.5")]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    device.expire_sessions();
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert_eq!(online.users.len(), 1);
+    assert_eq!(device.logins(), 2);
+    assert_eq!(*client.state().borrow(), ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn fetch_channel_status_returns_channels() {
+    let device = MockDevice::start();
+    device.set_channels(vec![
+        mock_device::channel(1, "192.168.254.2", true),
+        mock_device::channel(2, "192.168.254.3", false),
+    ]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let list = client.fetch_channel_status().await.unwrap();
+
+    assert_eq!(list.channels.len(), 2);
+    assert!(list.channels[0].online);
+    assert_eq!(list.channels[1].source.ip_address, "192.168.254.3");
+    assert_eq!(list.channels[1].detect_result, "netUnreachable");
+}
+
+#[tokio::test]
+async fn camera_has_no_channels() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    match client.fetch_channel_status().await {
+        Err(e) => assert!(e.is_unsupported()),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn fetch_storage_returns_disks() {
+    let device = MockDevice::start();
+    device.set_storage(vec![
+        mock_device::hdd(1, "ok", 1_907_729, 190_000),
+        mock_device::hdd(2, "smartFailed", 1_907_729, 0),
+    ]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let hdds = client.fetch_storage().await.unwrap().hdd_list.hdds;
+
+    assert_eq!(hdds.len(), 2);
+    assert!(hdds[0].is_ok());
+    assert_eq!(hdds[0].free_percent(), 9);
+    assert_eq!(hdds[1].status, "smartFailed");
+    assert!(!hdds[1].is_ok());
+}
+
+#[tokio::test]
+async fn fetch_picture_returns_jpeg() {
+    let device = MockDevice::start();
+    device.set_cameras(&[101]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let jpeg = client.fetch_picture(101).await.unwrap();
+    assert_eq!(jpeg, mock_device::picture(101));
+
+    match client.fetch_picture(201).await {
+        Err(ClientError::Device { status, .. }) => assert_eq!(status.as_u16(), 404),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn fetch_device_info_returns_info() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let info = client.fetch_device_info().await.unwrap();
+
+    assert_eq!(info.model, "DS-7608NI-K2");
+    assert_eq!(info.mac_address, "44:19:b6:a1:c2:d3");
+    assert_eq!(info.firmware_version, "V4.30.085");
+}
+
+#[tokio::test]
+async fn alert_stream_reads_events() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let mut alerts = client.open_alert_stream().await.unwrap();
+    device.push_alert("videoloss", "inactive", 0);
+    device.push_alert("illegalAccess", "active", 1);
+
+    let keep_alive = alerts.next().await.unwrap().unwrap();
+    assert!(!keep_alive.is_new());
+    let alert = alerts.next().await.unwrap().unwrap();
+    assert_eq!(alert.kind(), AlertKind::IllegalLogin);
+    assert_eq!(alert.channel_id, Some(1));
+    assert!(alert.is_new());
+}
+
+#[tokio::test]
+async fn alert_stream_relogins_after_cookie_expired() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    device.expire_sessions();
+    client.open_alert_stream().await.unwrap();
+
+    assert_eq!(device.logins(), 2);
+    assert_eq!(device.alert_streams(), 1);
+}
+
+fn log_query(minor_types: Vec<LogMinorType>) -> LogQuery {
+    LogQuery {
+        start: day().and_hms_opt(0, 0, 0).unwrap(),
+        end: day().succ_opt().unwrap().and_hms_opt(0, 0, 0).unwrap(),
+        minor_types,
+    }
+}
+
+#[test]
+fn log_query_is_in_device_local_time() {
+    let description = log_query(vec![LogMinorType::RemoteLogin]).search_description("id", 0, 50);
+
+    assert!(
+        description.contains("<startTime>2023-11-02T00:00:00</startTime>"),
+        "{}",
+        description
+    );
+    assert!(description.contains("<endTime>2023-11-03T00:00:00</endTime>"));
+}
+
+#[tokio::test]
+async fn search_log_filters_type_and_time() {
+    let device = MockDevice::start();
+    device.set_logs(vec![
+        mock_device::log("2023-11-01T23:59:00Z", "remoteLogin", "guard", "10.0.0.77"),
+        mock_device::log("2023-11-02T01:12:40Z", "remoteLogin", "guard", "10.0.0.77"),
+        mock_device::log("2023-11-02T01:40:02Z", "remoteLogout", "guard", "10.0.0.77"),
+        mock_device::log(
+            "2023-11-02T03:05:11Z",
+            "illegalLogin",
+            "admin",
+            "203.0.113.9",
+        ),
+    ]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let entries = client
+        .search_log(&log_query(vec![
+            LogMinorType::RemoteLogin,
+            LogMinorType::IllegalLogin,
+        ]))
+        .await
+        .unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].minor_type(), "remoteLogin");
+    assert_eq!(entries[0].time, "2023-11-02T01:12:40Z");
+    assert_eq!(entries[1].ip_address, "203.0.113.9");
+}
+
+#[tokio::test]
+async fn search_log_reads_every_page() {
+    let device = MockDevice::start();
+    device.set_logs(
+        (0..120)
+            .map(|i| {
+                let time = format!("2023-11-02T10:{:02}:{:02}Z", i / 60, i % 60);
+                mock_device::log(&time, "remoteLogin", "guard", "10.0.0.77")
+            })
+            .collect(),
+    );
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let entries = client
+        .search_log(&log_query(LogMinorType::ALL.to_vec()))
+        .await
+        .unwrap();
+
+    assert_eq!(entries.len(), 120);
+    assert_eq!(entries[119].time, "2023-11-02T10:01:59Z");
+}
+
+#[tokio::test]
+async fn deny_ip_adds_entry_once() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    client.deny_ip("203.0.113.9").await.unwrap();
+    client.deny_ip("203.0.113.9").await.unwrap();
+    client.deny_ip("198.51.100.4").await.unwrap();
+
+    let filter = device.ip_filter();
+    assert!(filter.denies("203.0.113.9"));
+    assert!(filter.denies("198.51.100.4"));
+    assert_eq!(filter.list.addresses.len(), 2);
+}
+
+#[tokio::test]
+async fn deny_ip_keeps_allow_list() {
+    let device = MockDevice::start();
+    let mut allow = device.ip_filter();
+    allow.deny("10.0.0.5").unwrap();
+    allow.permission_type = "allow".into();
+    assert!(allow.enabled);
+    device.set_ip_filter(allow.clone());
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    assert!(client.deny_ip("203.0.113.9").await.is_err());
+    assert_eq!(device.ip_filter(), allow);
+}
+
+#[test]
+fn deny_keeps_disabled_allow_list() {
+    let mut allow = IPFilter {
+        enabled: false,
+        permission_type: "deny".into(),
+        list: Default::default(),
+    };
+    allow.deny("10.0.0.5").unwrap();
+    allow.enabled = false;
+    allow.permission_type = "allow".into();
+    let before = allow.clone();
+
+    assert!(allow.deny("203.0.113.9").is_err());
+    assert_eq!(allow, before);
+}
+
+#[tokio::test]
+async fn deny_ip_keeps_disabled_deny_list() {
+    let device = MockDevice::start();
+    let mut old = device.ip_filter();
+    old.deny("10.0.0.5").unwrap();
+    old.enabled = false;
+    device.set_ip_filter(old.clone());
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    assert!(client.deny_ip("203.0.113.9").await.is_err());
+    assert_eq!(device.ip_filter(), old);
+}
+
+#[tokio::test]
+async fn remove_ip_filter_drops_entry() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+    client.deny_ip("203.0.113.9").await.unwrap();
+    let IPFilter { list, .. } = client.deny_ip("198.51.100.4").await.unwrap();
+
+    let filter = client.remove_ip_filter(list.addresses[0].id).await.unwrap();
+
+    assert_eq!(filter, device.ip_filter());
+    assert!(!filter.denies("203.0.113.9"));
+    assert!(filter.denies("198.51.100.4"));
+}
+
+#[tokio::test]
+async fn login_lock_round_trips() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let lock = client.fetch_login_lock().await.unwrap();
+    assert_eq!(lock.max_attempts, 5);
+
+    let stricter = IllegalLoginLock {
+        max_attempts: 3,
+        lock_duration: 60,
+        ..lock
+    };
+    client.update_login_lock(&stricter).await.unwrap();
+
+    assert_eq!(device.login_lock(), stricter);
+    assert_eq!(client.fetch_login_lock().await.unwrap(), stricter);
+}
+
+#[tokio::test]
+async fn heartbeat_reports_lost_session() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.set_heartbeat_interval(Duration::from_millis(50));
+    let state = client.state();
+    client.login().await.unwrap();
+
+    device.expire_sessions();
+
+    assert!(wait_for(|| *state.borrow() == ConnectionState::SessionLost).await);
+}
+
+#[tokio::test]
+async fn relogin_backs_off_until_device_is_back() {
+    let device = MockDevice::start();
+    device.set_users(vec![mock_device::user(1, USERNAME, "10.0.0.5")]);
+    let mut client = client(&device, PASSWORD);
+    client.set_heartbeat_interval(Duration::from_millis(50));
+    let state = client.state();
+    client.login().await.unwrap();
+
+    device.expire_sessions();
+    device.set_offline(true);
+    assert!(wait_for(|| *state.borrow() == ConnectionState::SessionLost).await);
+    let fetch = tokio::spawn(async move {
+        let online = client.fetch_online_users().await;
+        (client, online)
+    });
+
+    assert!(
+        wait_for(|| matches!(
+            *state.borrow(),
+            ConnectionState::Reconnecting { attempt: 1, .. }
+        ))
+        .await
+    );
+    device.set_offline(false);
+
+    let (client, online) = fetch.await.unwrap();
+    assert_eq!(online.unwrap().users.len(), 1);
+    assert_eq!(*client.state().borrow(), ConnectionState::Connected);
+}
+
+#[tokio::test]
+async fn slow_device_still_answers() {
+    let device = MockDevice::start();
+    device.set_users(vec![mock_device::user(1, USERNAME, "10.0.0.5")]);
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    device.set_delay(Duration::from_millis(300));
+    let started = Instant::now();
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(online.users.len(), 1);
+}
+
+#[tokio::test]
+async fn auto_mode_falls_back_to_digest() {
+    let device = MockDevice::start();
+    device.set_digest_only(true);
+    device.set_users(vec![mock_device::user(1, "guard", "10.0.0.77")]);
+    let mut client = client(&device, PASSWORD);
+
+    client.login().await.unwrap();
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert_eq!(online.users.len(), 1);
+    assert_eq!(device.logins(), 0);
+}
+
+#[tokio::test]
+async fn digest_mode_with_wrong_password_fails() {
+    let device = MockDevice::start();
+    device.set_digest_only(true);
+    let mut client = client(&device, "not-the-password").with_auth_mode(AuthMode::Digest);
+
+    assert!(matches!(client.login().await, Err(ClientError::Auth(_))));
+    assert_eq!(*client.state().borrow(), ConnectionState::Disconnected);
+}
+
+#[tokio::test]
+async fn digest_mode_answers_fresh_nonce() {
+    let device = MockDevice::start();
+    device.set_digest_only(true);
+    device.set_users(vec![mock_device::user(1, "guard", "10.0.0.77")]);
+    let mut client = client(&device, PASSWORD).with_auth_mode(AuthMode::Digest);
+    client.login().await.unwrap();
+
+    device.rotate_nonce();
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert_eq!(online.users.len(), 1);
+}
+
+#[tokio::test]
+async fn session_mode_does_not_fall_back() {
+    let device = MockDevice::start();
+    device.set_digest_only(true);
+    let mut client = client(&device, PASSWORD).with_auth_mode(AuthMode::Session);
+
+    assert!(client.login().await.is_err());
+}
+
+#[tokio::test]
+async fn debug_output_hides_credentials() {
+    let device = MockDevice::start();
+    let mut client = client(&device, PASSWORD);
+    client.login().await.unwrap();
+
+    let debug = format!("{:?}", client);
+    assert!(!debug.contains(PASSWORD), "{}", debug);
+    assert!(!debug.contains("WebSession_"), "{}", debug);
+    assert!(debug.contains("[redacted]"));
+
+    let login = SessionLogin::try_from(
+        "<SessionLogin>\
+            <userName>admin</userName>\
+            <password>5f4dcc3b5aa765d61d8327deb882cf99</password>\
+            <sessionID>3b7fa8c1d2e94c6b</sessionID>\
+            <isSessionIDValidLongTerm>false</isSessionIDValidLongTerm>\
+            <sessionIDVersion>2</sessionIDVersion>\
+        </SessionLogin>",
+    )
+    .unwrap();
+    assert_eq!(login.password.expose(), "5f4dcc3b5aa765d61d8327deb882cf99");
+    let debug = format!("{:?}", login);
+    assert!(!debug.contains("5f4dcc3b"), "{}", debug);
+    assert!(!debug.contains("3b7fa8c1"), "{}", debug);
+    assert_eq!(login.session_id.to_string(), "[redacted]");
+}
+
+fn tls_client(device: &MockDevice, tls: TlsOptions) -> HikClient<WebEndpoint> {
+    client(device, PASSWORD).with_tls(&tls).unwrap()
+}
+
+#[tokio::test]
+async fn https_with_pinned_fingerprint_connects() {
+    let device = MockDevice::start_tls();
+    device.set_users(vec![mock_device::user(1, "guard", "10.0.0.77")]);
+    let mut client = tls_client(
+        &device,
+        TlsOptions {
+            fingerprint: Some(sha256::digest(SERVER_CERT)),
+            ..Default::default()
+        },
+    );
+
+    client.login().await.unwrap();
+    let online = client.fetch_online_users().await.unwrap();
+
+    assert_eq!(online.users.len(), 1);
+    assert!(wait_for(|| device.heartbeats() > 0).await);
+}
+
+#[tokio::test]
+async fn https_with_other_fingerprint_fails() {
+    let device = MockDevice::start_tls();
+    let mut client = tls_client(
+        &device,
+        TlsOptions {
+            fingerprint: Some(sha256::digest("some other certificate")),
+            ..Default::default()
+        },
+    );
+
+    assert!(matches!(client.login().await, Err(ClientError::Tls(_))));
+}
+
+#[tokio::test]
+async fn https_with_ca_file_connects() {
+    let device = MockDevice::start_tls();
+    let mut client = tls_client(
+        &device,
+        TlsOptions {
+            ca_file: Some(CA_FILE.into()),
+            ..Default::default()
+        },
+    );
+
+    client.login().await.unwrap();
+}
+
+#[tokio::test]
+async fn https_insecure_connects() {
+    let device = MockDevice::start_tls();
+    let mut client = tls_client(
+        &device,
+        TlsOptions {
+            insecure: true,
+            ..Default::default()
+        },
+    );
+
+    client.login().await.unwrap();
+}
+
+#[tokio::test]
+async fn https_with_untrusted_certificate_fails() {
+    let device = MockDevice::start_tls();
+    let mut client = tls_client(&device, TlsOptions::default());
+
+    assert!(matches!(client.login().await, Err(ClientError::Tls(_))));
+}
+
+#[test]
+fn tls_options_are_exclusive() {
+    let tls = TlsOptions {
+        fingerprint: Some(sha256::digest(SERVER_CERT)),
+        insecure: true,
+        ..Default::default()
+    };
+
+    assert!(tls.build_client().is_err());
+}
+
+#[test]
+fn fingerprint_accepts_colon_separated_hex() {
+    let fingerprint = sha256::digest(SERVER_CERT)
+        .to_uppercase()
+        .as_bytes()
+        .chunks(2)
+        .map(|c| std::str::from_utf8(c).unwrap())
+        .collect::<Vec<&str>>()
+        .join(":");
+    let tls = TlsOptions {
+        fingerprint: Some(fingerprint),
+        ..Default::default()
+    };
+
+    assert!(tls.build_client().is_ok());
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Error, Result};
+use reqwest::Client;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use serde::Deserialize;
+use std::{fs, io::BufReader, path::PathBuf, sync::Arc, time::SystemTime};
+
+/// How to trust a device served over HTTPS. At most one option may be set;
+/// with none of them the bundled Mozilla roots are used.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct TlsOptions {
+    /// PEM file of the CA that signed the device certificate.
+    pub ca_file: Option<PathBuf>,
+
+    /// SHA-256 of the device certificate as hex, `:` separators allowed.
+    pub fingerprint: Option<String>,
+
+    /// Accept any certificate.
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+impl TlsOptions {
+    pub fn build_client(&self) -> Result<Client> {
+        let client = Client::builder()
+            .use_preconfigured_tls(self.rustls_config()?)
+            .build()?;
+
+        Ok(client)
+    }
+
+    /// What to trust, for HTTPS clients and plain TLS streams alike.
+    pub fn rustls_config(&self) -> Result<ClientConfig> {
+        let builder = ClientConfig::builder().with_safe_defaults();
+
+        let config = match (&self.ca_file, &self.fingerprint, self.insecure) {
+            (None, None, false) => {
+                let mut roots = RootCertStore::empty();
+                roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                }));
+                builder.with_root_certificates(roots).with_no_client_auth()
+            }
+            (Some(ca_file), None, false) => {
+                let pem = fs::read(ca_file)
+                    .with_context(|| format!("unable to read {}", ca_file.display()))?;
+                let mut roots = RootCertStore::empty();
+                let (added, _) = roots
+                    .add_parsable_certificates(&rustls_pemfile::certs(&mut BufReader::new(&*pem))?);
+                if added == 0 {
+                    return Err(Error::msg(format!(
+                        "no certificate in {}",
+                        ca_file.display()
+                    )));
+                }
+                builder.with_root_certificates(roots).with_no_client_auth()
+            }
+            (None, Some(fingerprint), false) => builder
+                .with_custom_certificate_verifier(Arc::new(PinnedCert {
+                    fingerprint: utils::normalize_fingerprint(fingerprint)?,
+                }))
+                .with_no_client_auth(),
+            (None, None, true) => builder
+                .with_custom_certificate_verifier(Arc::new(AnyCert))
+                .with_no_client_auth(),
+            _ => {
+                return Err(Error::msg(
+                    "only one of ca_file, fingerprint and insecure can be set",
+                ))
+            }
+        };
+
+        Ok(config)
+    }
+}
+
+/// Trusts exactly one certificate, whoever signed it.
+struct PinnedCert {
+    fingerprint: String,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if sha256::digest(end_entity.0.as_slice()) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "certificate does not match pinned fingerprint".into(),
+            ))
+        }
+    }
+}
+
+/// Trusts whatever it is shown.
+struct AnyCert;
+
+impl ServerCertVerifier for AnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+mod utils {
+    use anyhow::{Error, Result};
+
+    pub fn normalize_fingerprint(value: &str) -> Result<String> {
+        let hex = value
+            .chars()
+            .filter(|c| *c != ':' && !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::msg(format!(
+                "invalid SHA-256 fingerprint: {}",
+                value
+            )));
+        }
+
+        Ok(hex)
+    }
+}
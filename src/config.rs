@@ -1,30 +1,195 @@
-use anyhow::{Error, Result};
-use std::{env, fs, path::PathBuf};
+use anyhow::{Context, Error, Result};
+use std::{collections::HashSet, env, fs, path::PathBuf, time::Duration};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    time,
+};
 
 use serde::Deserialize;
 
+use crate::{
+    api_provider::WebEndpoint,
+    client::{AuthMode, ClientResult, HikClient, TlsOptions},
+    monitor::Monitor,
+    notify::{SmtpConfig, SyslogConfig, WebhookConfig},
+    rules::{Rule, RuleAction, Rules},
+    secret::Password,
+    tui::ThemeName,
+};
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
+    #[serde(rename = "device", default)]
+    pub devices: Vec<DeviceConfig>,
+
+    /// Where snapshots of new sessions are saved.
+    #[serde(default = "Config::default_snapshot_dir")]
+    pub snapshot_dir: PathBuf,
+
+    /// Where the session history is kept.
+    #[serde(default = "Config::default_data_dir")]
+    pub data_dir: PathBuf,
+
+    /// Days a past session stays in the history, 0 keeps it forever.
+    #[serde(default = "Config::default_history_retention_days")]
+    pub history_retention_days: u32,
+
+    /// How often devices are asked who is online, in milliseconds.
+    #[serde(default = "Config::default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+
+    /// `dark` or `light`.
+    #[serde(default)]
+    pub theme: ThemeName,
+
+    /// Checked in order against every session starting or ending.
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+
+    /// For sessions no rule matched.
+    #[serde(default)]
+    pub default_action: RuleAction,
+
+    /// Told about every session change, or only the ones a rule notifies
+    /// of, and about device connection changes.
+    #[serde(rename = "webhook", default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    /// Mails the sessions a rule notifies of as they start.
+    pub smtp: Option<SmtpConfig>,
+
+    /// Collectors sent every session change and device connection change.
+    #[serde(rename = "syslog", default)]
+    pub syslogs: Vec<SyslogConfig>,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct DeviceConfig {
+    #[serde(default = "DeviceConfig::default_name")]
+    pub name: String,
     pub endpoint: String,
     pub username: String,
-    pub password: String,
+    /// The password, or `{ env = .. }`, `{ file = .. }`, `{ command = [..] }`
+    /// or `{ keyring = "service" }` to read it from.
+    pub password: Password,
+    #[serde(default)]
+    pub auth: AuthMode,
+    #[serde(default)]
+    pub tls: TlsOptions,
+    /// Alarm when a disk has less free space left, in percent. Off with the
+    /// default 0, as recorders that overwrite old footage sit near 0% free.
+    #[serde(default)]
+    pub min_free_percent: u8,
+    /// Channels to take a picture of when someone new logs in, `101` being
+    /// the main stream of camera 1.
+    #[serde(default)]
+    pub snapshot_channels: Vec<u32>,
 }
+
 const CONFIG_FILENAME: &str = "Config.toml";
 
 impl Config {
-    pub fn read_env() -> Result<Self> {
+    /// Config.toml of the working directory, or else the one next to the
+    /// executable.
+    pub fn find() -> Result<PathBuf> {
         let from_pwd = env::current_dir()?.join(CONFIG_FILENAME);
         let from_exe = Self::exe_dir()?.join(CONFIG_FILENAME);
 
-        let setting_str = match fs::read_to_string(from_pwd).ok() {
-            Some(s) => Some(s),
-            None => fs::read_to_string(from_exe).ok(),
-        };
+        [from_pwd, from_exe]
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or_else(|| Error::msg("unable to find config file"))
+    }
+
+    /// Keeps only the device named `device` when given, then points the
+    /// device left at `endpoint` or logs in as `username`, which takes a
+    /// single one.
+    pub fn select(
+        &mut self,
+        device: Option<&str>,
+        endpoint: Option<&str>,
+        username: Option<&str>,
+    ) -> Result<()> {
+        if let Some(name) = device {
+            self.devices.retain(|d| d.name == name);
+            if self.devices.is_empty() {
+                return Err(Error::msg(format!("no device named {}", name)));
+            }
+        }
+
+        if endpoint.is_some() || username.is_some() {
+            let [device] = &mut self.devices[..] else {
+                return Err(Error::msg(
+                    "endpoint and username overrides need a single device",
+                ));
+            };
+            if let Some(endpoint) = endpoint {
+                device.endpoint = endpoint.into();
+            }
+            if let Some(username) = username {
+                device.username = username.into();
+            }
+        }
+
+        Ok(())
+    }
 
-        match setting_str {
-            Some(s) => Ok(toml::from_str(&s)?),
-            None => Err(Error::msg("unable to find config file")),
+    // top level keys of the single device from before [[device]] existed
+    const LEGACY_DEVICE_KEYS: [&'static str; 3] = ["endpoint", "username", "password"];
+
+    fn parse(s: &str) -> Result<Self> {
+        let mut conf: Config = toml::from_str(s)?;
+        // not a flattened Option, which takes any error in it for no device
+        let table = s.parse::<toml::Table>()?;
+        if Self::LEGACY_DEVICE_KEYS
+            .iter()
+            .any(|key| table.contains_key(*key))
+        {
+            let device = toml::from_str::<DeviceConfig>(s).context("invalid top level device")?;
+            conf.devices.insert(0, device);
+        }
+
+        if conf.devices.is_empty() {
+            return Err(Error::msg("no device configured"));
+        }
+        if conf.poll_interval_ms == 0 {
+            return Err(Error::msg("poll_interval_ms must be above 0"));
         }
+        let mut names = HashSet::new();
+        for device in &conf.devices {
+            if !names.insert(device.name.as_str()) {
+                return Err(Error::msg(format!(
+                    "duplicate device name: {}",
+                    device.name
+                )));
+            }
+        }
+
+        Ok(conf)
+    }
+
+    fn default_snapshot_dir() -> PathBuf {
+        "snapshots".into()
+    }
+
+    fn default_data_dir() -> PathBuf {
+        "data".into()
+    }
+
+    fn default_history_retention_days() -> u32 {
+        90
+    }
+
+    fn default_poll_interval_ms() -> u64 {
+        Monitor::POLL_INTERVAL.as_millis() as u64
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn rules(&self) -> Rules {
+        Rules::new(self.rules.clone(), self.default_action)
     }
 
     fn exe_dir() -> Result<PathBuf> {
@@ -33,3 +198,405 @@ impl Config {
         Ok(exe)
     }
 }
+
+/// The config file along with what the command line changed in it, so it
+/// reads the same every time.
+#[derive(Clone, Debug)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub device: Option<String>,
+    pub endpoint: Option<String>,
+    pub username: Option<String>,
+}
+
+impl ConfigSource {
+    pub fn load(&self) -> Result<Config> {
+        let s = fs::read_to_string(&self.path)
+            .with_context(|| format!("unable to read {}", self.path.display()))?;
+        self.parse(&s)
+    }
+
+    /// Sends the config read again every time the file changes, or why it
+    /// could not be, until the receiver is dropped.
+    pub fn watch(self, every: Duration) -> UnboundedReceiver<Result<Config>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // editors tend to replace the file rather than write into it, so
+            // it is looked up by path every time
+            let mut last = fs::read_to_string(&self.path).ok();
+            let mut interval = time::interval(every);
+            while !tx.is_closed() {
+                interval.tick().await;
+                // gone for a moment while being replaced
+                let Ok(s) = fs::read_to_string(&self.path) else {
+                    continue;
+                };
+                if last.as_ref() == Some(&s) {
+                    continue;
+                }
+
+                let conf = self.parse(&s);
+                last = Some(s);
+                if tx.send(conf).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    fn parse(&self, s: &str) -> Result<Config> {
+        let mut conf =
+            Config::parse(s).with_context(|| format!("invalid config {}", self.path.display()))?;
+        conf.select(
+            self.device.as_deref(),
+            self.endpoint.as_deref(),
+            self.username.as_deref(),
+        )?;
+
+        Ok(conf)
+    }
+}
+
+impl DeviceConfig {
+    /// A client for the device, not logged in yet.
+    pub async fn client(&self) -> ClientResult<HikClient<WebEndpoint>> {
+        let password = self
+            .password
+            .resolve(&self.username)
+            .await
+            .context("unable to get the password")?;
+        HikClient::new(
+            &self.username,
+            password.expose(),
+            WebEndpoint::new(&self.endpoint),
+        )
+        .with_auth_mode(self.auth)
+        .with_tls(&self.tls)
+    }
+
+    fn default_name() -> String {
+        "default".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        notify::{SyslogFormat, SyslogTransport},
+        secret::PasswordSource,
+        testing::temp_dir,
+    };
+
+    #[test]
+    fn parses_single_device_config() {
+        let conf = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.devices.len(), 1);
+        assert_eq!(conf.devices[0].name, "default");
+        assert_eq!(conf.devices[0].auth, AuthMode::Auto);
+        assert_eq!(conf.devices[0].min_free_percent, 0);
+        assert!(conf.devices[0].snapshot_channels.is_empty());
+        assert_eq!(conf.snapshot_dir, PathBuf::from("snapshots"));
+        assert_eq!(conf.data_dir, PathBuf::from("data"));
+        assert_eq!(conf.history_retention_days, 90);
+        assert_eq!(conf.poll_interval(), Monitor::POLL_INTERVAL);
+        assert_eq!(conf.theme, ThemeName::Dark);
+    }
+
+    #[test]
+    fn single_device_config_reports_bad_field() {
+        let error = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = { evn = "NVR_PASSWORD" }
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        let error = format!("{:#}", error);
+        assert!(error.contains("unknown variant `evn`"), "{}", error);
+    }
+
+    #[test]
+    fn parses_device_list() {
+        let conf = Config::parse(
+            r#"
+            snapshot_dir = "/var/lib/gusta/snapshots"
+            data_dir = "/var/lib/gusta"
+            history_retention_days = 0
+
+            [[device]]
+            name = "site-a"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[device]]
+            name = "site-b"
+            endpoint = "http://10.0.1.2"
+            username = "viewer"
+            password = "secret"
+            auth = "digest"
+            min_free_percent = 10
+            snapshot_channels = [101, 201]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.devices.len(), 2);
+        assert_eq!(conf.devices[1].name, "site-b");
+        assert_eq!(conf.devices[1].auth, AuthMode::Digest);
+        assert_eq!(conf.devices[1].min_free_percent, 10);
+        assert_eq!(conf.devices[1].snapshot_channels, vec![101, 201]);
+        assert_eq!(conf.snapshot_dir, PathBuf::from("/var/lib/gusta/snapshots"));
+        assert_eq!(conf.data_dir, PathBuf::from("/var/lib/gusta"));
+        assert_eq!(conf.history_retention_days, 0);
+    }
+
+    async fn next_reload(reloads: &mut UnboundedReceiver<Result<Config>>) -> Result<Config> {
+        time::timeout(Duration::from_secs(5), reloads.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn watch_sends_changed_config() {
+        let dir = temp_dir();
+        let path = dir.path().join("gusta.toml");
+        let device = |name: &str| {
+            format!(
+                "[[device]]\nname = \"{}\"\nendpoint = \"http://10.0.0.2\"\n\
+                 username = \"admin\"\npassword = \"secret\"\n",
+                name
+            )
+        };
+        fs::write(&path, device("site-a")).unwrap();
+        let source = ConfigSource {
+            path: path.clone(),
+            device: None,
+            endpoint: None,
+            username: Some("viewer".into()),
+        };
+        let mut reloads = source.watch(Duration::from_millis(20));
+        // let the watcher read the file as it is
+        time::sleep(Duration::from_millis(100)).await;
+
+        // the username override takes a single device
+        fs::write(&path, device("site-a") + &device("site-b")).unwrap();
+        assert!(next_reload(&mut reloads).await.is_err());
+
+        fs::write(
+            &path,
+            "poll_interval_ms = 0\n".to_string() + &device("site-a"),
+        )
+        .unwrap();
+        assert!(next_reload(&mut reloads).await.is_err());
+
+        fs::write(&path, "theme = \"light\"\n".to_string() + &device("site-b")).unwrap();
+        let conf = next_reload(&mut reloads).await.unwrap();
+        assert_eq!(conf.theme, ThemeName::Light);
+        assert_eq!(conf.devices[0].name, "site-b");
+        assert_eq!(conf.devices[0].username, "viewer");
+    }
+
+    #[test]
+    fn rejects_duplicate_device_names() {
+        let conf = Config::parse(
+            r#"
+            [[device]]
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[device]]
+            endpoint = "http://10.0.1.2"
+            username = "admin"
+            password = "secret"
+            "#,
+        );
+
+        assert!(conf.is_err());
+    }
+
+    #[test]
+    fn selects_and_overrides_device() {
+        let two = r#"
+            [[device]]
+            name = "site-a"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[device]]
+            name = "site-b"
+            endpoint = "http://10.0.1.2"
+            username = "admin"
+            password = "secret"
+            "#;
+
+        let mut conf = Config::parse(two).unwrap();
+        assert!(conf.select(None, None, Some("viewer")).is_err());
+        assert!(conf.select(Some("site-c"), None, None).is_err());
+
+        let mut conf = Config::parse(two).unwrap();
+        conf.select(Some("site-b"), Some("https://10.0.1.3"), Some("viewer"))
+            .unwrap();
+        assert_eq!(conf.devices.len(), 1);
+        assert_eq!(conf.devices[0].name, "site-b");
+        assert_eq!(conf.devices[0].endpoint, "https://10.0.1.3");
+        assert_eq!(conf.devices[0].username, "viewer");
+    }
+
+    #[test]
+    fn parses_rules() {
+        let conf = Config::parse(
+            r#"
+            default_action = "notify"
+
+            [[device]]
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[rule]]
+            name = "office"
+            ips = ["192.168.1.0/24"]
+            hours = ["08:00-18:00"]
+            action = "ignore"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.rules.len(), 1);
+        assert_eq!(conf.rules[0].action, RuleAction::Ignore);
+        assert_eq!(conf.default_action, RuleAction::Notify);
+    }
+
+    #[test]
+    fn parses_webhooks() {
+        let conf = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[webhook]]
+            url = "https://chat.example.com/hooks/abc"
+            events = ["session_started", "device_down"]
+            template = '{"text": "{{user}} on {{device}}"}'
+            headers = { Authorization = "Bearer token" }
+
+            [[webhook]]
+            url = "http://10.0.0.50/gusta"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.webhooks.len(), 2);
+        assert_eq!(conf.webhooks[0].events.len(), 2);
+        assert_eq!(conf.webhooks[0].headers["Authorization"], "Bearer token");
+        assert!(conf.webhooks[1].events.is_empty());
+        assert_eq!(conf.webhooks[1].retries, 3);
+        assert_eq!(conf.webhooks[1].retry_delay_ms, 1000);
+    }
+
+    #[test]
+    fn parses_smtp() {
+        let conf = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+            # only sessions a rule notifies of are mailed
+            default_action = "notify"
+
+            [[rule]]
+            ips = ["192.168.1.0/24"]
+            action = "sound"
+
+            [smtp]
+            host = "mail.example.com"
+            username = "gusta"
+            password = { env = "SMTP_PASSWORD" }
+            from = "gusta@example.com"
+            to = ["security@example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let smtp = conf.smtp.unwrap();
+        assert_eq!(smtp.port, 587);
+        assert!(smtp.starttls);
+        assert_eq!(smtp.batch_secs, 60);
+        assert_eq!(
+            smtp.password,
+            Some(Password::From(PasswordSource::Env("SMTP_PASSWORD".into())))
+        );
+    }
+
+    #[test]
+    fn parses_syslog_targets() {
+        let conf = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[syslog]]
+            host = "siem.example.com"
+
+            [[syslog]]
+            host = "10.0.0.60"
+            transport = "tls"
+            format = "cef"
+            tls = { ca_file = "/etc/gusta/siem-ca.pem" }
+            "#,
+        )
+        .unwrap();
+
+        let (udp, tls) = (&conf.syslogs[0], &conf.syslogs[1]);
+        assert_eq!(udp.transport, SyslogTransport::Udp);
+        assert_eq!(udp.format, SyslogFormat::Text);
+        assert_eq!(udp.port(), 514);
+        assert_eq!(udp.facility, 4);
+        assert_eq!(udp.sd_id, "gusta@32473");
+        assert_eq!(tls.port(), 6514);
+        assert_eq!(tls.format, SyslogFormat::Cef);
+        assert!(tls.tls.ca_file.is_some());
+    }
+
+    #[test]
+    fn rejects_bad_rule() {
+        let conf = Config::parse(
+            r#"
+            endpoint = "http://10.0.0.2"
+            username = "admin"
+            password = "secret"
+
+            [[rule]]
+            ips = ["192.168.1.0/33"]
+            action = "ignore"
+            "#,
+        );
+
+        assert!(conf.is_err());
+    }
+
+    #[test]
+    fn rejects_empty_config() {
+        assert!(Config::parse("").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use chrono::Local;
+use std::{future::Future, io::Write, sync::Arc};
+
+use crate::{
+    config::Config,
+    monitor::{Aggregator, DeviceChange, DeviceEvent, Monitor},
+    notify::{ErrorHandler, Notification, Notifier},
+    store::SessionStore,
+};
+
+/// Polls the devices like the TUI does, without a terminal, until
+/// `shutdown` completes. Sessions starting or ending, devices going down and
+/// relogins are written to `out` one JSON object per line, with the fields
+/// of [`Notification`]; its `version` goes up whenever one of them changes
+/// meaning or goes away.
+pub async fn run(
+    conf: &Config,
+    out: &mut impl Write,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let on_error: ErrorHandler = Arc::new(|e| eprintln!("notification lost: {}", e));
+    let notifier = Notifier::start(conf, on_error)?;
+    let rules = conf.rules();
+    let mut store = SessionStore::open(&conf.data_dir, conf.history_retention_days)?;
+
+    // pollers stop once this is dropped
+    let (monitor, mut updates) = Monitor::start(&conf.devices);
+    monitor.set_poll_interval(conf.poll_interval());
+    let mut aggregator = Aggregator::new(conf.devices.iter().map(|d| d.name.as_str()));
+    tokio::pin!(shutdown);
+    loop {
+        let update = tokio::select! {
+            update = updates.recv() => match update {
+                Some(update) => update,
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let users_changed = matches!(update.event, DeviceEvent::Users(_));
+        let now = Local::now();
+        for change in aggregator.apply(update) {
+            notifier.change(&change, &rules, now);
+            if is_event(&change) {
+                serde_json::to_writer(&mut *out, &Notification::change(&change, now))?;
+                writeln!(out)?;
+                out.flush()?;
+            }
+        }
+        if users_changed {
+            if let Err(e) = store.observe(&aggregator.online()) {
+                eprintln!("history not saved: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// sessions found at startup are no news, and connections coming and going
+// show as relogins and devices down
+fn is_event(change: &DeviceChange) -> bool {
+    match change {
+        DeviceChange::Session { initial, .. } => !initial,
+        DeviceChange::Relogin { .. } | DeviceChange::Down { .. } => true,
+        DeviceChange::Connected { .. } | DeviceChange::Disconnected { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::mock_device::{self, MockDevice, PASSWORD, USERNAME},
+        testing::temp_dir,
+    };
+    use serde_json::Value;
+    use std::{fs, io, sync::Mutex, time::Duration};
+    use tokio::{sync::oneshot, time};
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Lines {
+        fn events(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        }
+    }
+
+    async fn wait_for(condition: impl Fn() -> bool) {
+        time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_session_changes_and_relogins() {
+        let device = MockDevice::start();
+        device.set_users(vec![mock_device::user(2, "guard", "10.0.0.77")]);
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let conf: Config = toml::from_str(&format!(
+            r#"
+            data_dir = '{}'
+
+            [[device]]
+            name = "nvr"
+            endpoint = "{}"
+            username = "{}"
+            password = "{}"
+            auth = "session"
+            "#,
+            dir.display(),
+            device.endpoint(),
+            USERNAME,
+            PASSWORD
+        ))
+        .unwrap();
+
+        let lines = Lines::default();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let daemon = {
+            let mut lines = lines.clone();
+            tokio::spawn(async move {
+                run(&conf, &mut lines, async {
+                    let _ = stopped.await;
+                })
+                .await
+            })
+        };
+
+        // the first listing is in the history, and no news
+        let history = dir.join("history.jsonl");
+        wait_for(|| fs::read_to_string(&history).is_ok_and(|h| h.contains("guard"))).await;
+        device.set_users(vec![
+            mock_device::user(2, "guard", "10.0.0.77"),
+            mock_device::user(3, "guest", "10.0.0.9"),
+        ]);
+        wait_for(|| !lines.events().is_empty()).await;
+        device.expire_sessions();
+        wait_for(|| lines.events().len() == 2).await;
+        stop.send(()).unwrap();
+        daemon.await.unwrap().unwrap();
+
+        let events = lines.events();
+        assert_eq!(events[0]["version"], 1);
+        assert_eq!(events[0]["event"], "session_started");
+        assert_eq!(events[0]["device"], "nvr");
+        assert_eq!(events[0]["user"], "guest");
+        assert_eq!(events[0]["ip"], "10.0.0.9");
+        assert_eq!(events[1]["event"], "relogin");
+        assert_eq!(events[1]["device"], "nvr");
+        assert!(events[1].get("user").is_none());
+    }
+}
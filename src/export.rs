@@ -0,0 +1,226 @@
+use anyhow::Result;
+use chrono::{NaiveDate, SecondsFormat};
+use std::{fmt, io::Write, str::FromStr};
+
+use crate::store::SessionRecord;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Csv, ExportFormat::Json];
+
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown format {}, expected csv or json", s))
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension().to_uppercase())
+    }
+}
+
+/// Which sessions to export, a field left empty matching every session.
+#[derive(Clone, Debug, Default)]
+pub struct ExportFilter {
+    /// Sessions still online on or after this day.
+    pub from: Option<NaiveDate>,
+    /// Sessions that started on or before this day.
+    pub to: Option<NaiveDate>,
+    pub user: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, record: &SessionRecord) -> bool {
+        self.from
+            .is_none_or(|from| record.last_seen.date_naive() >= from)
+            && self
+                .to
+                .is_none_or(|to| record.first_seen.date_naive() <= to)
+            && self
+                .user
+                .as_ref()
+                .is_none_or(|user| record.user.eq_ignore_ascii_case(user))
+            && self.ip.as_ref().is_none_or(|ip| record.ip == *ip)
+    }
+}
+
+/// Writes the sessions matching `filter` oldest first, returning how many
+/// there were.
+pub fn export<'a>(
+    records: impl IntoIterator<Item = &'a SessionRecord>,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> Result<usize> {
+    let mut records = records
+        .into_iter()
+        .filter(|r| filter.matches(r))
+        .collect::<Vec<&SessionRecord>>();
+    records.sort_by_key(|r| r.first_seen);
+
+    match format {
+        ExportFormat::Csv => write_csv(&records, out)?,
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &records)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()?;
+
+    Ok(records.len())
+}
+
+fn write_csv(records: &[&SessionRecord], out: &mut impl Write) -> Result<()> {
+    writeln!(
+        out,
+        "device,user,user_type,ip,login_time,first_seen,last_seen,snapshot"
+    )?;
+    for r in records {
+        let snapshot = r
+            .snapshot
+            .as_ref()
+            .map(|s| s.display().to_string())
+            .unwrap_or_default();
+        let fields = [
+            r.device.as_str(),
+            &r.user,
+            &r.user_type,
+            &r.ip,
+            &r.login_time,
+            &r.first_seen.to_rfc3339_opts(SecondsFormat::Secs, false),
+            &r.last_seen.to_rfc3339_opts(SecondsFormat::Secs, false),
+            &snapshot,
+        ];
+        let line = fields
+            .iter()
+            .map(|f| csv_field(f))
+            .collect::<Vec<String>>()
+            .join(",");
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{at, day};
+    use chrono::{DateTime, Duration, Local};
+
+    fn record(user: &str, ip: &str, first_seen: DateTime<Local>) -> SessionRecord {
+        SessionRecord {
+            first_seen,
+            last_seen: first_seen + chrono::Duration::hours(1),
+            device: "nvr".into(),
+            user: user.into(),
+            user_type: "operator".into(),
+            ip: ip.into(),
+            login_time: "2023-11-02T08:00:00+07:00".into(),
+            snapshot: None,
+        }
+    }
+
+    fn csv(records: &[SessionRecord], filter: &ExportFilter) -> String {
+        let mut out = vec![];
+        export(records, filter, ExportFormat::Csv, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn filters_by_date_user_and_ip() {
+        let records = [
+            record("admin", "10.0.0.2", at(8, 0) - Duration::days(1)),
+            record("guest", "10.0.0.9", at(8, 0)),
+            record("admin", "10.0.0.9", at(8, 0) + Duration::days(1)),
+        ];
+
+        let by_date = ExportFilter {
+            from: Some(day()),
+            to: Some(day()),
+            ..Default::default()
+        };
+        let by_user = ExportFilter {
+            user: Some("ADMIN".into()),
+            ..Default::default()
+        };
+        let by_ip = ExportFilter {
+            ip: Some("10.0.0.9".into()),
+            ..Default::default()
+        };
+        let count = |filter: &ExportFilter| records.iter().filter(|r| filter.matches(r)).count();
+
+        assert_eq!(count(&by_date), 1);
+        assert_eq!(count(&by_user), 2);
+        assert_eq!(count(&by_ip), 2);
+        assert_eq!(count(&ExportFilter::default()), 3);
+    }
+
+    #[test]
+    fn csv_is_sorted_and_quoted() {
+        let records = vec![
+            record("guest", "10.0.0.9", at(8, 0)),
+            record("Doe, \"J\"", "10.0.0.2", at(8, 0) - Duration::days(1)),
+        ];
+
+        let out = csv(&records, &ExportFilter::default());
+        let lines = out.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("nvr,\"Doe, \"\"J\"\"\",operator,10.0.0.2,"));
+        assert!(lines[2].starts_with("nvr,guest,"));
+    }
+
+    #[test]
+    fn json_keeps_every_field() {
+        let records = vec![record("admin", "10.0.0.2", at(8, 0))];
+
+        let mut out = vec![];
+        let count = export(
+            &records,
+            &ExportFilter::default(),
+            ExportFormat::Json,
+            &mut out,
+        )
+        .unwrap();
+        let parsed: Vec<SessionRecord> = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(parsed, records);
+    }
+
+    #[test]
+    fn parses_format() {
+        assert_eq!("CSV".parse(), Ok(ExportFormat::Csv));
+        assert_eq!("json".parse(), Ok(ExportFormat::Json));
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
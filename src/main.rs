@@ -1,18 +1,155 @@
 use anyhow::Result;
+use clap::Parser;
+use cli::{Cli, Command, DaemonArgs, Exit, ExportArgs, OnceArgs};
 use config::*;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    process::ExitCode,
+    time::Duration,
+};
+use store::SessionStore;
 use tui::AppTui;
 
 mod api_provider;
 mod assets;
+mod cli;
 mod client;
 mod config;
+mod daemon;
+mod export;
+mod monitor;
+mod notify;
+mod probe;
+mod rules;
+mod secret;
+mod store;
+#[cfg(test)]
+mod testing;
 mod tui;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let conf = Config::read_env()?;
-    let mut app = AppTui::new(conf)?;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let source = match config_source(&cli) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            return Exit::Config.into();
+        }
+    };
+    let conf = match source.load() {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            return Exit::Config.into();
+        }
+    };
+
+    let exit = match cli.command.unwrap_or(Command::Watch) {
+        Command::Watch => watch(conf, source).await,
+        Command::Once(args) => once(&conf, &args).await,
+        Command::Check => check(&conf).await,
+        Command::Export(args) => export(&conf, &args),
+        Command::Daemon(args) => daemon(&conf, &args).await,
+    };
+
+    match exit {
+        Ok(exit) => exit.into(),
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            Exit::Failure.into()
+        }
+    }
+}
+
+fn config_source(cli: &Cli) -> Result<ConfigSource> {
+    let path = match &cli.config {
+        Some(path) => path.clone(),
+        None => Config::find()?,
+    };
+
+    Ok(ConfigSource {
+        path,
+        device: cli.device.clone(),
+        endpoint: cli.endpoint.clone(),
+        username: cli.username.clone(),
+    })
+}
+
+async fn watch(conf: Config, source: ConfigSource) -> Result<Exit> {
+    let reloads = source.watch(Duration::from_secs(1));
+    let mut app = AppTui::new(conf, reloads)?;
     app.start().await?;
 
-    Ok(())
+    Ok(Exit::Ok)
+}
+
+async fn once(conf: &Config, args: &OnceArgs) -> Result<Exit> {
+    let mut exit = Exit::Ok;
+    let mut users = vec![];
+    for (device, online) in probe::online_users(&conf.devices).await {
+        match online {
+            Ok(online) => users.extend(online),
+            Err(e) => {
+                eprintln!("{}: {:#}", device, e);
+                exit = exit.max(Exit::of(&e));
+            }
+        }
+    }
+    probe::write_users(&users, args.format, &mut io::stdout().lock())?;
+
+    Ok(exit)
+}
+
+async fn check(conf: &Config) -> Result<Exit> {
+    let mut exit = Exit::Ok;
+    for (device, login) in probe::check(&conf.devices).await {
+        match login {
+            Ok(()) => println!("{}: ok", device),
+            Err(e) => {
+                println!("{}: {:#}", device, e);
+                exit = exit.max(Exit::of(&e));
+            }
+        }
+    }
+
+    Ok(exit)
+}
+
+async fn daemon(conf: &Config, args: &DaemonArgs) -> Result<Exit> {
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    match &args.output {
+        Some(path) => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            daemon::run(conf, &mut file, shutdown).await?
+        }
+        None => daemon::run(conf, &mut io::stdout(), shutdown).await?,
+    }
+
+    Ok(Exit::Ok)
+}
+
+fn export(conf: &Config, args: &ExportArgs) -> Result<Exit> {
+    let records = SessionStore::load(&conf.data_dir)?;
+    let count = match &args.output {
+        Some(path) => export::export(
+            &records,
+            &args.filter(),
+            args.format,
+            &mut File::create(path)?,
+        )?,
+        None => export::export(
+            &records,
+            &args.filter(),
+            args.format,
+            &mut io::stdout().lock(),
+        )?,
+    };
+    eprintln!("exported {} sessions", count);
+
+    Ok(Exit::Ok)
 }
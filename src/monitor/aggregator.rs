@@ -0,0 +1,246 @@
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+
+use crate::client::{
+    ClientError, ConnectionState, DeviceInfo, InputProxyChannelStatus, OnlineUser,
+};
+
+use super::{DeviceChannel, DeviceDisk, DeviceEvent, DeviceUpdate, DeviceUser, DiskHealth};
+use crate::rules::{session_changes, SessionChange};
+
+/// What an update changed that the outside world may want to hear about.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceChange {
+    /// `initial` when found on the first listing of the device, so maybe
+    /// started long before gusta did.
+    Session {
+        change: SessionChange,
+        user: DeviceUser,
+        initial: bool,
+    },
+    Connected {
+        device: String,
+    },
+    /// Connected again after having been connected before.
+    Relogin {
+        device: String,
+    },
+    /// `state` being what the connection turned into.
+    Disconnected {
+        device: String,
+        state: String,
+    },
+    /// Once per outage, see [`Aggregator::down`].
+    Down {
+        device: String,
+        error: String,
+    },
+}
+
+struct DeviceView {
+    state: ConnectionState,
+    error: Option<String>,
+    users: Vec<OnlineUser>,
+    info: Option<DeviceInfo>,
+    channels: Vec<InputProxyChannelStatus>,
+    disks: Vec<DiskHealth>,
+    locked_until: Option<DateTime<Local>>,
+    // network errors since users were last listed
+    unreachable: u32,
+    listed: bool,
+    was_connected: bool,
+    down_reported: bool,
+}
+
+impl DeviceView {
+    fn new() -> Self {
+        DeviceView {
+            state: ConnectionState::Disconnected,
+            error: None,
+            users: vec![],
+            info: None,
+            channels: vec![],
+            disks: vec![],
+            locked_until: None,
+            unreachable: 0,
+            listed: false,
+            was_connected: false,
+            down_reported: false,
+        }
+    }
+}
+
+/// Merges per device updates into one view of every monitored device.
+pub struct Aggregator {
+    devices: BTreeMap<String, DeviceView>,
+}
+
+impl Aggregator {
+    // a dropped alert stream alone is not an outage
+    const DOWN_AFTER: u32 = 3;
+
+    pub fn new<'a>(names: impl Iterator<Item = &'a str>) -> Self {
+        let devices = names.map(|n| (n.to_string(), DeviceView::new())).collect();
+
+        Aggregator { devices }
+    }
+
+    /// Follows a new device list, forgetting about the devices gone from it,
+    /// sessions included, and keeping what is known of the others.
+    pub fn set_devices<'a>(&mut self, names: impl Iterator<Item = &'a str>) {
+        let mut devices = BTreeMap::new();
+        for name in names {
+            let view = self.devices.remove(name).unwrap_or_else(DeviceView::new);
+            devices.insert(name.to_string(), view);
+        }
+        self.devices = devices;
+    }
+
+    /// Takes in `update`, returning what it changed.
+    pub fn apply(&mut self, update: DeviceUpdate) -> Vec<DeviceChange> {
+        let device = update.device;
+        let view = match self.devices.get_mut(&device) {
+            Some(v) => v,
+            None => return vec![],
+        };
+        let mut changes = vec![];
+
+        match update.event {
+            DeviceEvent::State(state) => {
+                view.locked_until = match &state {
+                    ConnectionState::Locked { unlock_in } => chrono::Duration::from_std(*unlock_in)
+                        .ok()
+                        .map(|d| Local::now() + d),
+                    _ => None,
+                };
+                let was = view.state == ConnectionState::Connected;
+                match (was, state == ConnectionState::Connected) {
+                    (false, true) if view.was_connected => changes.push(DeviceChange::Relogin {
+                        device: device.clone(),
+                    }),
+                    (false, true) => changes.push(DeviceChange::Connected {
+                        device: device.clone(),
+                    }),
+                    (true, false) => changes.push(DeviceChange::Disconnected {
+                        device: device.clone(),
+                        state: state.to_string(),
+                    }),
+                    _ => {}
+                }
+                if state == ConnectionState::Connected {
+                    view.unreachable = 0;
+                    view.was_connected = true;
+                }
+                view.state = state;
+                view.error = None;
+            }
+            DeviceEvent::Users(users) => {
+                let listing = |users: &[OnlineUser]| {
+                    users
+                        .iter()
+                        .map(|u| DeviceUser::new(&device, u.clone()))
+                        .collect::<Vec<DeviceUser>>()
+                };
+                let initial = !view.listed;
+                changes.extend(
+                    session_changes(&listing(&view.users), &listing(&users))
+                        .into_iter()
+                        .map(|(change, user)| DeviceChange::Session {
+                            change,
+                            user,
+                            initial,
+                        }),
+                );
+                view.listed = true;
+                view.users = users;
+                view.error = None;
+                view.unreachable = 0;
+            }
+            DeviceEvent::Info(info) => view.info = Some(info),
+            DeviceEvent::Channels(channels) => view.channels = channels,
+            DeviceEvent::Storage(disks) => view.disks = disks,
+            // events are a log, not device state
+            DeviceEvent::Alert(_) => {}
+            DeviceEvent::Error(e) => {
+                if let ClientError::Network(_) = e {
+                    view.unreachable += 1;
+                }
+                view.error = Some(e.to_string());
+            }
+        }
+
+        let down = self.down(&device);
+        let view = self.devices.get_mut(&device).unwrap();
+        match down {
+            Some(error) if !view.down_reported => {
+                view.down_reported = true;
+                changes.push(DeviceChange::Down { device, error });
+            }
+            Some(_) => {}
+            None => view.down_reported = false,
+        }
+
+        changes
+    }
+
+    pub fn online(&self) -> Vec<DeviceUser> {
+        self.devices
+            .iter()
+            .flat_map(|(name, view)| view.users.iter().map(|u| DeviceUser::new(name, u.clone())))
+            .collect()
+    }
+
+    pub fn channels(&self) -> Vec<DeviceChannel> {
+        self.devices
+            .iter()
+            .flat_map(|(name, view)| {
+                view.channels
+                    .iter()
+                    .map(|c| DeviceChannel::new(name, c.clone()))
+            })
+            .collect()
+    }
+
+    pub fn disks(&self) -> Vec<DeviceDisk> {
+        self.devices
+            .iter()
+            .flat_map(|(name, view)| view.disks.iter().map(|d| DeviceDisk::new(name, d.clone())))
+            .collect()
+    }
+
+    pub fn infos(&self) -> impl Iterator<Item = (&str, Option<&DeviceInfo>)> {
+        self.devices
+            .iter()
+            .map(|(name, view)| (name.as_str(), view.info.as_ref()))
+    }
+
+    /// Devices that locked out the account gusta logs in with.
+    pub fn locks(&self) -> impl Iterator<Item = (&str, DateTime<Local>)> {
+        self.devices
+            .iter()
+            .filter_map(|(name, view)| view.locked_until.map(|t| (name.as_str(), t)))
+    }
+
+    /// Why the device is down, once logging in failed or a few polls in a
+    /// row could not reach it.
+    pub fn down(&self, device: &str) -> Option<String> {
+        let view = self.devices.get(device)?;
+        match &view.state {
+            ConnectionState::Reconnecting { .. } => Some(view.state.to_string()),
+            _ if view.unreachable >= Self::DOWN_AFTER => view.error.clone(),
+            _ => None,
+        }
+    }
+
+    /// One line per device summary, e.g. `nvr-1: connected | nvr-2: error: ...`.
+    pub fn status(&self) -> String {
+        self.devices
+            .iter()
+            .map(|(name, view)| match &view.error {
+                Some(e) => format!("{}: error: {}", name, e),
+                None => format!("{}: {}", name, view.state),
+            })
+            .collect::<Vec<String>>()
+            .join(" | ")
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Error;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+use crate::client::{ClientError, ClientResult, IPFilter, IllegalLoginLock, LogEntry, LogQuery};
+
+/// Work handed to a device poller, run on its logged in client between polls.
+pub enum Command {
+    SearchLog(LogQuery, oneshot::Sender<ClientResult<Vec<LogEntry>>>),
+    FetchIpFilter(oneshot::Sender<ClientResult<IPFilter>>),
+    DenyIp(String, oneshot::Sender<ClientResult<IPFilter>>),
+    RemoveIpFilter(u32, oneshot::Sender<ClientResult<IPFilter>>),
+    FetchLoginLock(oneshot::Sender<ClientResult<IllegalLoginLock>>),
+    UpdateLoginLock(IllegalLoginLock, oneshot::Sender<ClientResult<()>>),
+    FetchPicture(u32, oneshot::Sender<ClientResult<Vec<u8>>>),
+}
+
+/// Cheap to clone handle for asking devices things outside the poll loop,
+/// following the devices the monitor is told to poll.
+#[derive(Clone, Default)]
+pub struct Control {
+    commands: Arc<RwLock<BTreeMap<String, UnboundedSender<Command>>>>,
+}
+
+impl Control {
+    pub(super) fn insert(&self, device: &str, commands: UnboundedSender<Command>) {
+        self.commands
+            .write()
+            .unwrap()
+            .insert(device.into(), commands);
+    }
+
+    pub(super) fn remove(&self, device: &str) {
+        self.commands.write().unwrap().remove(device);
+    }
+
+    /// Names of the devices polled right now, in order.
+    pub fn devices(&self) -> Vec<String> {
+        self.commands.read().unwrap().keys().cloned().collect()
+    }
+
+    pub async fn search_log(&self, device: &str, query: LogQuery) -> ClientResult<Vec<LogEntry>> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::SearchLog(query, tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn ip_filter(&self, device: &str) -> ClientResult<IPFilter> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::FetchIpFilter(tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn deny_ip(&self, device: &str, ip: &str) -> ClientResult<IPFilter> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::DenyIp(ip.into(), tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn remove_ip_filter(&self, device: &str, id: u32) -> ClientResult<IPFilter> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::RemoveIpFilter(id, tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn login_lock(&self, device: &str) -> ClientResult<IllegalLoginLock> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::FetchLoginLock(tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn update_login_lock(
+        &self,
+        device: &str,
+        lock: IllegalLoginLock,
+    ) -> ClientResult<()> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::UpdateLoginLock(lock, tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    pub async fn picture(&self, device: &str, channel: u32) -> ClientResult<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        self.send(device, Command::FetchPicture(channel, tx))?;
+
+        rx.await.map_err(|_| ClientError::NotConnected)?
+    }
+
+    fn send(&self, device: &str, command: Command) -> ClientResult<()> {
+        let commands = self.commands.read().unwrap();
+        let poller = commands
+            .get(device)
+            .ok_or(Error::msg(format!("unknown device: {}", device)))?;
+
+        // the poller is gone once the device rejected our credentials
+        poller.send(command).map_err(|_| ClientError::NotConnected)
+    }
+}
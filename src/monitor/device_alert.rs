@@ -0,0 +1,17 @@
+use crate::client::EventNotificationAlert;
+
+/// An event together with the device that raised it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceAlert {
+    pub device: String,
+    pub alert: EventNotificationAlert,
+}
+
+impl DeviceAlert {
+    pub fn new(device: &str, alert: EventNotificationAlert) -> Self {
+        DeviceAlert {
+            device: device.into(),
+            alert,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::hash::{Hash, Hasher};
+
+use crate::client::{Hashable, InputProxyChannelStatus};
+
+/// A camera channel together with the recorder it belongs to.
+#[derive(Clone, Debug)]
+pub struct DeviceChannel {
+    pub device: String,
+    pub channel: InputProxyChannelStatus,
+}
+
+impl DeviceChannel {
+    pub fn new(device: &str, channel: InputProxyChannelStatus) -> Self {
+        DeviceChannel {
+            device: device.into(),
+            channel,
+        }
+    }
+}
+
+// the same channel whatever its state, so history keeps one entry per channel
+impl PartialEq for DeviceChannel {
+    fn eq(&self, other: &Self) -> bool {
+        self.device == other.device && self.channel.id == other.channel.id
+    }
+}
+
+impl Hash for DeviceChannel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.device.hash(state);
+        self.channel.id.hash(state);
+    }
+}
+
+impl Hashable for DeviceChannel {}
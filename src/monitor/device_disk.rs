@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::client::Hdd;
+
+/// What is wrong with a disk.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiskProblem {
+    Status(String),
+    LowSpace { free_percent: u8 },
+}
+
+impl DiskProblem {
+    /// A disk not in `ok` state, or with less than `min_free_percent` left.
+    pub fn check(hdd: &Hdd, min_free_percent: u8) -> Option<Self> {
+        if !hdd.is_ok() {
+            return Some(DiskProblem::Status(hdd.status.clone()));
+        }
+
+        let free_percent = hdd.free_percent();
+        if free_percent < min_free_percent {
+            return Some(DiskProblem::LowSpace { free_percent });
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for DiskProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskProblem::Status(status) => write!(f, "status {}", status),
+            DiskProblem::LowSpace { free_percent } => write!(f, "only {}% free", free_percent),
+        }
+    }
+}
+
+/// A disk as checked by the poller of its device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskHealth {
+    pub hdd: Hdd,
+    pub problem: Option<DiskProblem>,
+}
+
+/// A disk together with the recorder it is in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceDisk {
+    pub device: String,
+    pub disk: DiskHealth,
+}
+
+impl DeviceDisk {
+    pub fn new(device: &str, disk: DiskHealth) -> Self {
+        DeviceDisk {
+            device: device.into(),
+            disk,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use crate::client::{Hashable, OnlineUser};
+
+/// An online user together with the device it is logged into.
+#[derive(Clone, Debug, Eq)]
+pub struct DeviceUser {
+    pub device: String,
+    pub user: OnlineUser,
+    /// Pictures taken when the session showed up, not part of its identity.
+    pub snapshot: Option<PathBuf>,
+}
+
+impl DeviceUser {
+    pub fn new(device: &str, user: OnlineUser) -> Self {
+        DeviceUser {
+            device: device.into(),
+            user,
+            snapshot: None,
+        }
+    }
+}
+
+impl PartialEq for DeviceUser {
+    fn eq(&self, other: &Self) -> bool {
+        self.device == other.device && self.user == other.user
+    }
+}
+
+impl Hash for DeviceUser {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.device.hash(state);
+        self.user.hash(state);
+    }
+}
+
+impl Hashable for DeviceUser {}
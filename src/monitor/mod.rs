@@ -0,0 +1,442 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    api_provider::WebEndpoint,
+    client::{
+        AlertStream, ClientError, ClientResult, ConnectionState, DeviceInfo,
+        EventNotificationAlert, HikClient, InputProxyChannelStatus, OnlineUser,
+    },
+    config::DeviceConfig,
+};
+
+pub use aggregator::*;
+pub use control::*;
+pub use device_alert::*;
+pub use device_channel::*;
+pub use device_disk::*;
+pub use device_user::*;
+pub use snapshot::*;
+
+mod aggregator;
+mod control;
+mod device_alert;
+mod device_channel;
+mod device_disk;
+mod device_user;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug)]
+pub enum DeviceEvent {
+    State(ConnectionState),
+    /// Users currently online, minus the account gusta logs in with.
+    Users(Vec<OnlineUser>),
+    /// Fetched again after every login.
+    Info(DeviceInfo),
+    /// Camera channels of a recorder, refreshed every few polls.
+    Channels(Vec<InputProxyChannelStatus>),
+    /// Disks of a recorder, checked against the configured free space.
+    Storage(Vec<DiskHealth>),
+    /// New events from the device's alert stream.
+    Alert(EventNotificationAlert),
+    Error(ClientError),
+}
+
+#[derive(Debug)]
+pub struct DeviceUpdate {
+    pub device: String,
+    pub event: DeviceEvent,
+}
+
+/// Polls every configured device on its own task, so one device going down
+/// never holds up the others.
+pub struct Monitor {
+    pollers: HashMap<String, (DeviceConfig, JoinHandle<()>)>,
+    control: Control,
+    updates: UnboundedSender<DeviceUpdate>,
+    poll_interval: watch::Sender<Duration>,
+    // None for the client's own
+    heartbeat_interval: Option<Duration>,
+}
+
+// what woke a poller up
+enum Wake {
+    Tick,
+    Alert(ClientResult<Option<EventNotificationAlert>>),
+    Command(Command),
+}
+
+impl Monitor {
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(1111);
+    // channels change slowly and recorders have lots of them
+    const FETCH_CHANNELS_EVERY: u32 = 5;
+    const FETCH_STORAGE_EVERY: u32 = 30;
+    // ticks between attempts to reopen the alert stream, doubled from one
+    // up to this while the device refuses it
+    const ALERTS_MAX_BACKOFF: u32 = 64;
+
+    pub fn start(devices: &[DeviceConfig]) -> (Self, UnboundedReceiver<DeviceUpdate>) {
+        let (updates, rx) = mpsc::unbounded_channel();
+        let (poll_interval, _) = watch::channel(Self::POLL_INTERVAL);
+        let mut monitor = Monitor {
+            pollers: HashMap::new(),
+            control: Control::default(),
+            updates,
+            poll_interval,
+            heartbeat_interval: None,
+        };
+        monitor.set_devices(devices);
+
+        (monitor, rx)
+    }
+
+    /// Starts polling the devices new to `devices` and stops polling the ones
+    /// gone from it. A device whose config changed is logged into again.
+    pub fn set_devices(&mut self, devices: &[DeviceConfig]) {
+        let control = &self.control;
+        self.pollers.retain(|name, (config, poller)| {
+            let keep = devices.contains(config);
+            if !keep {
+                poller.abort();
+                control.remove(name);
+            }
+            keep
+        });
+
+        for device in devices {
+            if self.pollers.contains_key(&device.name) {
+                continue;
+            }
+            let (command_tx, command_rx) = mpsc::unbounded_channel();
+            self.control.insert(&device.name, command_tx);
+            let poller = Self::spawn_poller(
+                device.clone(),
+                self.updates.clone(),
+                command_rx,
+                self.poll_interval.subscribe(),
+                self.heartbeat_interval,
+            );
+            self.pollers
+                .insert(device.name.clone(), (device.clone(), poller));
+        }
+    }
+
+    /// How often every device is asked who is online, from its next poll on.
+    pub fn set_poll_interval(&self, interval: Duration) {
+        self.poll_interval.send_replace(interval);
+    }
+
+    /// Heartbeats of the devices started from now on come every `interval`.
+    #[cfg(test)]
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = Some(interval);
+    }
+
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// Builds the client of `device`, retrying with the relogin backoff while
+    /// its password can't be had. The first failure is reported, and commands
+    /// meanwhile are dropped, which their askers see as not connected. None
+    /// once nobody listens anymore.
+    async fn client(
+        device: &DeviceConfig,
+        tx: &UnboundedSender<DeviceUpdate>,
+        commands: &mut UnboundedReceiver<Command>,
+    ) -> Option<HikClient<WebEndpoint>> {
+        let mut attempt = 0;
+        loop {
+            let e = match device.client().await {
+                Ok(client) => return Some(client),
+                Err(e) => e,
+            };
+            attempt += 1;
+            if attempt == 1 {
+                let update = DeviceUpdate {
+                    device: device.name.clone(),
+                    event: DeviceEvent::Error(e),
+                };
+                if tx.send(update).is_err() {
+                    return None;
+                }
+            }
+
+            let retry = time::sleep(HikClient::<WebEndpoint>::relogin_delay(attempt));
+            tokio::pin!(retry);
+            loop {
+                tokio::select! {
+                    _ = &mut retry => break,
+                    command = commands.recv() => match command {
+                        Some(command) => drop(command),
+                        // the monitor is gone
+                        None => return None,
+                    },
+                }
+            }
+        }
+    }
+
+    fn spawn_poller(
+        device: DeviceConfig,
+        tx: UnboundedSender<DeviceUpdate>,
+        mut commands: UnboundedReceiver<Command>,
+        mut poll_interval: watch::Receiver<Duration>,
+        heartbeat_interval: Option<Duration>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let Some(mut client) = Self::client(&device, &tx, &mut commands).await else {
+                return;
+            };
+            if let Some(interval) = heartbeat_interval {
+                client.set_heartbeat_interval(interval);
+            }
+
+            let mut logins = client.state();
+            let mut state = client.state();
+            let state_tx = tx.clone();
+            let name = device.name.clone();
+            // ends once the client and its state sender are dropped
+            tokio::spawn(async move {
+                loop {
+                    let update = DeviceUpdate {
+                        device: name.clone(),
+                        event: DeviceEvent::State(state.borrow_and_update().clone()),
+                    };
+                    if state_tx.send(update).is_err() || state.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            if let Err(e) = client.connect().await {
+                let _ = tx.send(DeviceUpdate {
+                    device: device.name.clone(),
+                    event: DeviceEvent::Error(e),
+                });
+                return;
+            }
+
+            let mut alerts: Option<AlertStream> = None;
+            let mut alerts_supported = true;
+            let mut alerts_due: u32 = 0;
+            let mut alerts_failures: u32 = 0;
+            let mut channels_supported = true;
+            let mut channels_due: u32 = 0;
+            let mut storage_supported = true;
+            let mut storage_due: u32 = 0;
+            let mut interval = time::interval(*poll_interval.borrow_and_update());
+            loop {
+                let wake = tokio::select! {
+                    _ = interval.tick() => Wake::Tick,
+                    Ok(()) = poll_interval.changed() => {
+                        interval = time::interval(*poll_interval.borrow_and_update());
+                        continue;
+                    }
+                    alert = Self::next_alert(&mut alerts) => Wake::Alert(alert),
+                    Some(command) = commands.recv() => Wake::Command(command),
+                };
+                match wake {
+                    Wake::Tick => {}
+                    Wake::Command(command) => {
+                        Self::run(&mut client, command).await;
+                        continue;
+                    }
+                    Wake::Alert(alert) => {
+                        let event = match alert {
+                            Ok(Some(alert)) if alert.is_new() => DeviceEvent::Alert(alert),
+                            Ok(Some(_)) => continue,
+                            // reopened on the next tick
+                            Ok(None) => {
+                                alerts = None;
+                                continue;
+                            }
+                            Err(e) => {
+                                alerts = None;
+                                DeviceEvent::Error(e)
+                            }
+                        };
+                        let update = DeviceUpdate {
+                            device: device.name.clone(),
+                            event,
+                        };
+                        if tx.send(update).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+
+                let event = match client.fetch_online_users().await {
+                    Ok(online) => DeviceEvent::Users(
+                        online
+                            .users
+                            .into_iter()
+                            .filter(|u| u.name != device.username)
+                            .collect(),
+                    ),
+                    Err(e) => DeviceEvent::Error(e),
+                };
+
+                // rejected credentials won't fix themselves, stop polling
+                let rejected = matches!(event, DeviceEvent::Error(ClientError::Auth(_)));
+                let update = DeviceUpdate {
+                    device: device.name.clone(),
+                    event,
+                };
+                if tx.send(update).is_err() || rejected {
+                    break;
+                }
+
+                // the device may have been swapped or upgraded while we were away
+                if logins.has_changed().unwrap_or(false)
+                    && *logins.borrow_and_update() == ConnectionState::Connected
+                {
+                    let event = match client.fetch_device_info().await {
+                        Ok(info) => DeviceEvent::Info(info),
+                        Err(e) => DeviceEvent::Error(e),
+                    };
+                    let update = DeviceUpdate {
+                        device: device.name.clone(),
+                        event,
+                    };
+                    if tx.send(update).is_err() {
+                        break;
+                    }
+                }
+
+                if alerts.is_none() && alerts_supported && alerts_due == 0 {
+                    match client.open_alert_stream().await {
+                        Ok(stream) => {
+                            alerts = Some(stream);
+                            alerts_failures = 0;
+                        }
+                        Err(e) if e.is_unsupported() => alerts_supported = false,
+                        Err(e) => {
+                            alerts_due = 2u32
+                                .saturating_pow(alerts_failures)
+                                .min(Self::ALERTS_MAX_BACKOFF);
+                            alerts_failures += 1;
+                            // once per outage, not on every attempt
+                            if alerts_failures == 1 {
+                                let update = DeviceUpdate {
+                                    device: device.name.clone(),
+                                    event: DeviceEvent::Error(e),
+                                };
+                                if tx.send(update).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                alerts_due = alerts_due.saturating_sub(1);
+
+                if channels_supported && channels_due == 0 {
+                    channels_due = Self::FETCH_CHANNELS_EVERY;
+                    let event = match client.fetch_channel_status().await {
+                        Ok(list) => Some(DeviceEvent::Channels(list.channels)),
+                        Err(e) if e.is_unsupported() => {
+                            channels_supported = false;
+                            None
+                        }
+                        Err(e) => Some(DeviceEvent::Error(e)),
+                    };
+                    let update = event.map(|event| DeviceUpdate {
+                        device: device.name.clone(),
+                        event,
+                    });
+                    if update.is_some_and(|u| tx.send(u).is_err()) {
+                        break;
+                    }
+                }
+                channels_due = channels_due.saturating_sub(1);
+
+                if storage_supported && storage_due == 0 {
+                    storage_due = Self::FETCH_STORAGE_EVERY;
+                    let event = match client.fetch_storage().await {
+                        Ok(storage) => Some(DeviceEvent::Storage(
+                            storage
+                                .hdd_list
+                                .hdds
+                                .into_iter()
+                                .map(|hdd| DiskHealth {
+                                    problem: DiskProblem::check(&hdd, device.min_free_percent),
+                                    hdd,
+                                })
+                                .collect(),
+                        )),
+                        Err(e) if e.is_unsupported() => {
+                            storage_supported = false;
+                            None
+                        }
+                        Err(e) => Some(DeviceEvent::Error(e)),
+                    };
+                    let update = event.map(|event| DeviceUpdate {
+                        device: device.name.clone(),
+                        event,
+                    });
+                    if update.is_some_and(|u| tx.send(u).is_err()) {
+                        break;
+                    }
+                }
+                storage_due = storage_due.saturating_sub(1);
+            }
+        })
+    }
+
+    async fn run(client: &mut HikClient<WebEndpoint>, command: Command) {
+        // nobody to tell when the asker went away
+        match command {
+            Command::SearchLog(query, reply) => {
+                let _ = reply.send(client.search_log(&query).await);
+            }
+            Command::FetchIpFilter(reply) => {
+                let _ = reply.send(client.fetch_ip_filter().await);
+            }
+            Command::DenyIp(ip, reply) => {
+                let _ = reply.send(client.deny_ip(&ip).await);
+            }
+            Command::RemoveIpFilter(id, reply) => {
+                let _ = reply.send(client.remove_ip_filter(id).await);
+            }
+            Command::FetchLoginLock(reply) => {
+                let _ = reply.send(client.fetch_login_lock().await);
+            }
+            Command::UpdateLoginLock(lock, reply) => {
+                let _ = reply.send(client.update_login_lock(&lock).await);
+            }
+            Command::FetchPicture(channel, reply) => {
+                let _ = reply.send(client.fetch_picture(channel).await);
+            }
+        }
+    }
+
+    /// Pending forever while there is no stream, so it can sit in a `select!`.
+    async fn next_alert(
+        alerts: &mut Option<AlertStream>,
+    ) -> ClientResult<Option<EventNotificationAlert>> {
+        match alerts {
+            Some(stream) => stream.next().await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        for (_, poller) in self.pollers.values() {
+            poller.abort();
+        }
+    }
+}
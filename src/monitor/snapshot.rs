@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use std::path::PathBuf;
+use tokio::fs;
+
+use super::{Control, DeviceUser};
+
+/// Camera pictures taken when someone logs in, one directory per session.
+#[derive(Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Snapshots { dir: dir.into() }
+    }
+
+    /// Where the pictures of `user` taken at `at` are saved.
+    pub fn session_dir(&self, user: &DeviceUser, at: DateTime<Local>) -> PathBuf {
+        let name = format!(
+            "{}_{}_{}_{}",
+            at.format("%Y%m%d-%H%M%S"),
+            user.device,
+            user.user.name,
+            user.user.client_address.ip_address
+        );
+        // device and user names end up in a path
+        let name = name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+
+        self.dir.join(name)
+    }
+
+    /// Saves `ch<id>.jpg` for each of `channels` of the device `user` logged
+    /// into. Fails only when not a single picture could be taken.
+    pub async fn capture(
+        &self,
+        control: &Control,
+        user: &DeviceUser,
+        channels: &[u32],
+        at: DateTime<Local>,
+    ) -> Result<PathBuf> {
+        let dir = self.session_dir(user, at);
+        let mut error = None;
+        let mut saved = 0;
+        for &channel in channels {
+            match control.picture(&user.device, channel).await {
+                Ok(jpeg) => {
+                    fs::create_dir_all(&dir).await?;
+                    fs::write(dir.join(format!("ch{}.jpg", channel)), jpeg).await?;
+                    saved += 1;
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+
+        match error {
+            Some(e) if saved == 0 => Err(e.into()),
+            _ => Ok(dir),
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::time::Duration;
+
+use chrono::Local;
+use tokio::time;
+
+use super::{
+    Aggregator, DeviceChange, DeviceEvent, DeviceUpdate, DeviceUser, DiskProblem, Monitor,
+    Snapshots,
+};
+use crate::{
+    client::{
+        mock_device::{self, MockDevice, PASSWORD, USERNAME},
+        AuthMode, ConnectionState, LogMinorType, LogQuery,
+    },
+    config::DeviceConfig,
+    rules::SessionChange,
+    secret::{Password, PasswordSource},
+    testing::{self, day, temp_dir},
+};
+
+fn device(name: &str, endpoint: &str) -> DeviceConfig {
+    DeviceConfig {
+        name: name.into(),
+        endpoint: endpoint.into(),
+        username: USERNAME.into(),
+        password: Password::Plain(PASSWORD.into()),
+        auth: AuthMode::Session,
+        tls: Default::default(),
+        min_free_percent: 5,
+        snapshot_channels: vec![],
+    }
+}
+
+#[tokio::test]
+async fn device_down_does_not_block_others() {
+    let up = MockDevice::start();
+    up.set_users(vec![
+        mock_device::user(1, USERNAME, "10.0.0.5"),
+        mock_device::user(2, "guard", "10.0.0.77"),
+    ]);
+    let devices = vec![
+        device("down", "http://127.0.0.1:1"),
+        device("up", &up.endpoint()),
+    ];
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    let users = time::timeout(Duration::from_secs(5), async {
+        loop {
+            let update = updates.recv().await.unwrap();
+            if let DeviceEvent::Users(users) = update.event {
+                assert_eq!(update.device, "up");
+                return users;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    // the account gusta logs in with is left out
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].name, "guard");
+}
+
+#[tokio::test]
+async fn missing_password_is_retried() {
+    let up = MockDevice::start();
+    let dir = temp_dir();
+    let password_file = dir.path().join("password");
+    let mut device = device("up", &up.endpoint());
+    device.password = Password::From(PasswordSource::File(password_file.clone()));
+
+    let (_monitor, mut updates) = Monitor::start(&[device]);
+
+    let update = time::timeout(Duration::from_secs(5), updates.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(update.event, DeviceEvent::Error(_)));
+
+    std::fs::write(&password_file, PASSWORD).unwrap();
+    time::timeout(Duration::from_secs(5), async {
+        while up.logins() == 0 {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    // reported once, not on every attempt
+    while let Ok(update) = updates.try_recv() {
+        assert!(!matches!(update.event, DeviceEvent::Error(_)));
+    }
+}
+
+#[tokio::test]
+async fn device_info_is_refreshed_after_relogin() {
+    let up = MockDevice::start();
+    let devices = vec![device("up", &up.endpoint())];
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    for logins in 1..=2 {
+        let info = time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let DeviceEvent::Info(info) = updates.recv().await.unwrap().event {
+                    return info;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(info.model, "DS-7608NI-K2");
+        assert_eq!(up.logins(), logins);
+        up.expire_sessions();
+    }
+}
+
+#[tokio::test]
+async fn only_new_alerts_are_forwarded() {
+    let up = MockDevice::start();
+    let devices = vec![device("up", &up.endpoint())];
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    assert!(time::timeout(Duration::from_secs(5), async {
+        while up.alert_streams() == 0 {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .is_ok());
+    up.push_alert("videoloss", "inactive", 0);
+    up.push_alert("VMD", "active", 2);
+    up.push_alert("diskerror", "active", 1);
+
+    let alert = time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let DeviceEvent::Alert(alert) = updates.recv().await.unwrap().event {
+                return alert;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(alert.event_type, "diskerror");
+}
+
+#[tokio::test]
+async fn refused_alert_stream_backs_off() {
+    let busy = MockDevice::start();
+    busy.set_alerts_busy(true);
+    let (monitor, mut updates) = Monitor::start(&[device("busy", &busy.endpoint())]);
+    monitor.set_poll_interval(Duration::from_millis(20));
+
+    // some 50 ticks
+    time::sleep(Duration::from_secs(1)).await;
+    let mut errors = 0;
+    while let Ok(update) = updates.try_recv() {
+        if matches!(update.event, DeviceEvent::Error(_)) {
+            errors += 1;
+        }
+    }
+    assert_eq!(errors, 1);
+    assert!(busy.alert_stream_requests() < 10);
+
+    busy.set_alerts_busy(false);
+    assert!(time::timeout(Duration::from_secs(5), async {
+        while busy.alert_streams() == 0 {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .is_ok());
+}
+
+#[tokio::test]
+async fn control_searches_device_log() {
+    let up = MockDevice::start();
+    up.set_logs(vec![mock_device::log(
+        "2023-11-02T01:12:40Z",
+        "remoteLogin",
+        "guard",
+        "10.0.0.77",
+    )]);
+    let devices = vec![device("up", &up.endpoint())];
+    let (monitor, _updates) = Monitor::start(&devices);
+    let query = LogQuery {
+        start: day().and_hms_opt(0, 0, 0).unwrap(),
+        end: day().succ_opt().unwrap().and_hms_opt(0, 0, 0).unwrap(),
+        minor_types: LogMinorType::ALL.to_vec(),
+    };
+
+    let entries = time::timeout(
+        Duration::from_secs(5),
+        monitor.control().search_log("up", query.clone()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].user_name, "guard");
+    assert!(monitor.control().search_log("nope", query).await.is_err());
+}
+
+#[tokio::test]
+async fn locked_account_shows_until_when() {
+    let locked = MockDevice::start();
+    locked.lock_account(1800);
+    let devices = vec![device("locked", &locked.endpoint())];
+    let mut aggregator = Aggregator::new(devices.iter().map(|d| d.name.as_str()));
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    time::timeout(Duration::from_secs(5), async {
+        while aggregator.locks().next().is_none() {
+            aggregator.apply(updates.recv().await.unwrap());
+        }
+    })
+    .await
+    .unwrap();
+
+    let (name, until) = aggregator.locks().next().unwrap();
+    assert_eq!(name, "locked");
+    let unlock_in = until - Local::now();
+    assert!(
+        unlock_in > chrono::Duration::minutes(29) && unlock_in <= chrono::Duration::minutes(30)
+    );
+}
+
+#[tokio::test]
+async fn channel_status_is_polled() {
+    let nvr = MockDevice::start();
+    nvr.set_channels(vec![
+        mock_device::channel(1, "192.168.254.2", true),
+        mock_device::channel(2, "192.168.254.3", true),
+    ]);
+    let devices = vec![device("nvr", &nvr.endpoint())];
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    let channels = time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let DeviceEvent::Channels(channels) = updates.recv().await.unwrap().event {
+                return channels;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(channels.len(), 2);
+    assert!(channels.iter().all(|c| c.online));
+}
+
+#[tokio::test]
+async fn storage_is_polled_with_problems() {
+    let nvr = MockDevice::start();
+    nvr.set_storage(vec![
+        mock_device::hdd(1, "ok", 1000, 500),
+        mock_device::hdd(2, "ok", 1000, 20),
+        mock_device::hdd(3, "error", 1000, 500),
+    ]);
+    let devices = vec![device("nvr", &nvr.endpoint())];
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    let disks = time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let DeviceEvent::Storage(disks) = updates.recv().await.unwrap().event {
+                return disks;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    let problems = disks.into_iter().map(|d| d.problem).collect::<Vec<_>>();
+    assert_eq!(
+        problems,
+        vec![
+            None,
+            Some(DiskProblem::LowSpace { free_percent: 2 }),
+            Some(DiskProblem::Status("error".into())),
+        ]
+    );
+}
+
+#[test]
+fn full_disk_is_fine_without_min_free_percent() {
+    let full = mock_device::hdd(1, "ok", 1000, 0);
+
+    assert_eq!(DiskProblem::check(&full, 0), None);
+    assert_eq!(
+        DiskProblem::check(&full, 5),
+        Some(DiskProblem::LowSpace { free_percent: 0 })
+    );
+}
+
+#[tokio::test]
+async fn snapshots_are_saved_per_session() {
+    let nvr = MockDevice::start();
+    nvr.set_cameras(&[101, 201]);
+    let devices = vec![device("nvr", &nvr.endpoint())];
+    let (monitor, _updates) = Monitor::start(&devices);
+
+    let dir = temp_dir();
+    let snapshots = Snapshots::new(dir.path());
+    let user = DeviceUser::new("nvr", mock_device::user(3, "guest", "10.0.0.9"));
+    let at = testing::at(8, 30);
+
+    // channel 301 has no camera, the others are still saved
+    let saved = snapshots
+        .capture(&monitor.control(), &user, &[101, 201, 301], at)
+        .await
+        .unwrap();
+
+    assert_eq!(saved, dir.path().join("20231102-083000_nvr_guest_10.0.0.9"));
+    assert_eq!(
+        std::fs::read(saved.join("ch101.jpg")).unwrap(),
+        mock_device::picture(101)
+    );
+    assert!(saved.join("ch201.jpg").exists());
+    assert!(!saved.join("ch301.jpg").exists());
+    assert!(snapshots
+        .capture(&monitor.control(), &user, &[301], at)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn unreachable_device_is_down() {
+    let up = MockDevice::start();
+    let devices = vec![
+        device("down", "http://127.0.0.1:1"),
+        device("up", &up.endpoint()),
+    ];
+    let mut aggregator = Aggregator::new(devices.iter().map(|d| d.name.as_str()));
+
+    let (_monitor, mut updates) = Monitor::start(&devices);
+
+    time::timeout(Duration::from_secs(5), async {
+        while aggregator.down("down").is_none() {
+            aggregator.apply(updates.recv().await.unwrap());
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(aggregator.down("down").unwrap().contains("login attempt"));
+    assert_eq!(aggregator.down("up"), None);
+}
+
+#[test]
+fn changes_are_reported_once() {
+    let mut aggregator = Aggregator::new(["nvr"].into_iter());
+    let mut apply = |event| {
+        aggregator.apply(DeviceUpdate {
+            device: "nvr".into(),
+            event,
+        })
+    };
+    let (guard, guest) = (
+        mock_device::user(2, "guard", "10.0.0.77"),
+        mock_device::user(3, "guest", "10.0.0.9"),
+    );
+    let retrying = |attempt| {
+        DeviceEvent::State(ConnectionState::Reconnecting {
+            attempt,
+            retry_in: Duration::from_secs(5),
+        })
+    };
+
+    assert_eq!(
+        apply(DeviceEvent::State(ConnectionState::Connected)),
+        [DeviceChange::Connected {
+            device: "nvr".into()
+        }]
+    );
+    assert_eq!(
+        apply(DeviceEvent::Users(vec![guard.clone()])),
+        [DeviceChange::Session {
+            change: SessionChange::Started,
+            user: DeviceUser::new("nvr", guard.clone()),
+            initial: true,
+        }]
+    );
+    assert_eq!(
+        apply(DeviceEvent::Users(vec![guest.clone()])),
+        [
+            DeviceChange::Session {
+                change: SessionChange::Started,
+                user: DeviceUser::new("nvr", guest),
+                initial: false,
+            },
+            DeviceChange::Session {
+                change: SessionChange::Ended,
+                user: DeviceUser::new("nvr", guard),
+                initial: false,
+            }
+        ]
+    );
+    assert_eq!(
+        apply(DeviceEvent::State(ConnectionState::SessionLost)),
+        [DeviceChange::Disconnected {
+            device: "nvr".into(),
+            state: "session lost".into(),
+        }]
+    );
+    assert!(matches!(
+        &apply(retrying(1))[..],
+        [DeviceChange::Down { .. }]
+    ));
+    assert!(apply(retrying(2)).is_empty());
+    assert_eq!(
+        apply(DeviceEvent::State(ConnectionState::Connected)),
+        [DeviceChange::Relogin {
+            device: "nvr".into()
+        }]
+    );
+}
+
+#[tokio::test]
+async fn set_devices_starts_and_stops_pollers() {
+    let (a, b) = (MockDevice::start(), MockDevice::start());
+    b.set_users(vec![mock_device::user(2, "guard", "10.0.0.77")]);
+
+    let (mut monitor, mut updates) = Monitor::start(&[device("a", &a.endpoint())]);
+    monitor.set_poll_interval(Duration::from_millis(50));
+    let control = monitor.control();
+    assert_eq!(control.devices(), ["a"]);
+
+    monitor.set_devices(&[device("a", &a.endpoint()), device("b", &b.endpoint())]);
+    assert_eq!(control.devices(), ["a", "b"]);
+    let users = time::timeout(Duration::from_secs(5), async {
+        loop {
+            let update = updates.recv().await.unwrap();
+            match update.event {
+                DeviceEvent::Users(users) if update.device == "b" => return users,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(users[0].name, "guard");
+    // a kept polling without logging in again
+    assert_eq!(a.logins(), 1);
+
+    monitor.set_devices(&[device("b", &b.endpoint())]);
+    assert_eq!(control.devices(), ["b"]);
+    assert!(control.ip_filter("a").await.is_err());
+
+    let mut aggregator = Aggregator::new(["a", "b"].into_iter());
+    aggregator.apply(DeviceUpdate {
+        device: "b".into(),
+        event: DeviceEvent::Users(users),
+    });
+    aggregator.set_devices(["b", "c"].into_iter());
+    assert_eq!(aggregator.online().len(), 1);
+    assert_eq!(
+        aggregator.infos().map(|(name, _)| name).collect::<Vec<_>>(),
+        ["b", "c"]
+    );
+}
+
+#[tokio::test]
+async fn changed_device_ends_old_session() {
+    let mock = MockDevice::start();
+    let (mut monitor, _updates) = Monitor::start(&[]);
+    monitor.set_heartbeat_interval(Duration::from_millis(200));
+    monitor.set_devices(&[device("nvr", &mock.endpoint())]);
+    time::timeout(Duration::from_secs(5), async {
+        while mock.heartbeats() == 0 {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut changed = device("nvr", &mock.endpoint());
+    changed.min_free_percent = 10;
+    monitor.set_devices(&[changed]);
+    time::timeout(Duration::from_secs(5), async {
+        while mock.logouts() == 0 || mock.logins() < 2 {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // a few heartbeat intervals
+    time::sleep(Duration::from_millis(1000)).await;
+    assert_eq!(mock.stale_heartbeats(), 0);
+}
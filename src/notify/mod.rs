@@ -0,0 +1,212 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    config::Config,
+    monitor::{DeviceChange, DeviceUser},
+    rules::{RuleAction, Rules, SessionChange},
+};
+
+pub use smtp::*;
+pub use syslog::*;
+pub use webhook::*;
+
+mod smtp;
+mod syslog;
+#[cfg(test)]
+mod tests;
+mod webhook;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    SessionStarted,
+    SessionEnded,
+    DeviceDown,
+    DeviceConnected,
+    DeviceDisconnected,
+    Relogin,
+}
+
+impl NotificationEvent {
+    /// As serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::SessionStarted => "session_started",
+            NotificationEvent::SessionEnded => "session_ended",
+            NotificationEvent::DeviceDown => "device_down",
+            NotificationEvent::DeviceConnected => "device_connected",
+            NotificationEvent::DeviceDisconnected => "device_disconnected",
+            NotificationEvent::Relogin => "relogin",
+        }
+    }
+}
+
+/// The session a notification is about.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SessionInfo {
+    pub user: String,
+    pub ip: String,
+    pub user_type: String,
+    /// As reported by the device.
+    pub login_time: String,
+}
+
+/// Something worth telling the outside world about.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Notification {
+    /// Bumped whenever a field changes meaning or goes away.
+    pub version: u32,
+    pub event: NotificationEvent,
+    pub time: DateTime<Local>,
+    pub device: String,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Notification {
+    pub const VERSION: u32 = 1;
+
+    pub fn session(change: SessionChange, user: &DeviceUser, time: DateTime<Local>) -> Self {
+        let event = match change {
+            SessionChange::Started => NotificationEvent::SessionStarted,
+            SessionChange::Ended => NotificationEvent::SessionEnded,
+        };
+        let session = SessionInfo {
+            user: user.user.name.clone(),
+            ip: user.user.client_address.ip_address.clone(),
+            user_type: user.user.user_type.clone(),
+            login_time: user.user.login_time.clone(),
+        };
+
+        Notification {
+            version: Self::VERSION,
+            event,
+            time,
+            device: user.device.clone(),
+            session: Some(session),
+            error: None,
+        }
+    }
+
+    pub fn device_down(device: &str, error: &str, time: DateTime<Local>) -> Self {
+        Self::device(NotificationEvent::DeviceDown, device, Some(error), time)
+    }
+
+    pub fn device_connected(device: &str, time: DateTime<Local>) -> Self {
+        Self::device(NotificationEvent::DeviceConnected, device, None, time)
+    }
+
+    /// `state` is what the connection turned into.
+    pub fn device_disconnected(device: &str, state: &str, time: DateTime<Local>) -> Self {
+        Self::device(
+            NotificationEvent::DeviceDisconnected,
+            device,
+            Some(state),
+            time,
+        )
+    }
+
+    pub fn relogin(device: &str, time: DateTime<Local>) -> Self {
+        Self::device(NotificationEvent::Relogin, device, None, time)
+    }
+
+    pub fn change(change: &DeviceChange, time: DateTime<Local>) -> Self {
+        match change {
+            DeviceChange::Session { change, user, .. } => Self::session(*change, user, time),
+            DeviceChange::Connected { device } => Self::device_connected(device, time),
+            DeviceChange::Relogin { device } => Self::relogin(device, time),
+            DeviceChange::Disconnected { device, state } => {
+                Self::device_disconnected(device, state, time)
+            }
+            DeviceChange::Down { device, error } => Self::device_down(device, error, time),
+        }
+    }
+
+    fn device(
+        event: NotificationEvent,
+        device: &str,
+        error: Option<&str>,
+        time: DateTime<Local>,
+    ) -> Self {
+        Notification {
+            version: Self::VERSION,
+            event,
+            time,
+            device: device.into(),
+            session: None,
+            error: error.map(String::from),
+        }
+    }
+}
+
+/// Told about deliveries that failed for good.
+pub type ErrorHandler = Arc<dyn Fn(String) + Send + Sync>;
+
+/// Hands changes to every configured target, never waiting on them.
+#[derive(Clone)]
+pub struct Notifier {
+    webhooks: Vec<Webhook>,
+    syslogs: Vec<SyslogTarget>,
+    mailer: Option<Mailer>,
+}
+
+impl Notifier {
+    pub fn start(config: &Config, on_error: ErrorHandler) -> Result<Self> {
+        let webhooks = config
+            .webhooks
+            .iter()
+            .map(|c| Webhook::start(c.clone(), on_error.clone()))
+            .collect::<Result<Vec<Webhook>>>()?;
+        let syslogs = config
+            .syslogs
+            .iter()
+            .map(|c| SyslogTarget::start(c.clone(), on_error.clone()))
+            .collect::<Result<Vec<SyslogTarget>>>()?;
+        let mailer = config
+            .smtp
+            .clone()
+            .map(|c| Mailer::start(c, on_error))
+            .transpose()?;
+
+        Ok(Notifier {
+            webhooks,
+            syslogs,
+            mailer,
+        })
+    }
+
+    /// Tells the targets about `change`, returning what the rules made of
+    /// it. Webhooks and syslog hear of every change, mail of the sessions a
+    /// rule notifies of, all leaving out the sessions found when gusta
+    /// started.
+    pub fn change(&self, change: &DeviceChange, rules: &Rules, at: DateTime<Local>) -> RuleAction {
+        let notification = Notification::change(change, at);
+        let (action, initial) = match change {
+            DeviceChange::Session {
+                change,
+                user,
+                initial,
+            } => (rules.evaluate(user, *change, at).0, *initial),
+            _ => (RuleAction::Ignore, false),
+        };
+
+        if !initial {
+            for webhook in &self.webhooks {
+                webhook.send(&notification, action);
+            }
+            for syslog in &self.syslogs {
+                syslog.send(&notification);
+            }
+            if let Some(mailer) = &self.mailer {
+                mailer.send(&notification, action);
+            }
+        }
+
+        action
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Error, Result};
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, AsyncSmtpTransportBuilder},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time::{self, Instant},
+};
+
+use super::{ErrorHandler, Notification, NotificationEvent};
+use crate::{rules::RuleAction, secret::Password};
+
+/// The `[smtp]` table of the config. Only sessions a rule notifies of are
+/// mailed, and the default action only sounds: mailing the sessions from
+/// outside the allowed ranges takes `default_action = "notify"` and a rule
+/// with those `ips` and `action = "sound"` or `"ignore"`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "SmtpConfig::default_port")]
+    pub port: u16,
+    /// Upgrades the connection before logging in, plain text otherwise.
+    #[serde(default = "SmtpConfig::default_starttls")]
+    pub starttls: bool,
+    pub username: Option<String>,
+    /// Read anew for every mail, as a device password is for every login.
+    pub password: Option<Password>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Sessions starting within this many seconds of the first one go in
+    /// the same mail.
+    #[serde(default = "SmtpConfig::default_batch_secs")]
+    pub batch_secs: u64,
+}
+
+impl SmtpConfig {
+    fn default_port() -> u16 {
+        587
+    }
+
+    fn default_starttls() -> bool {
+        true
+    }
+
+    fn default_batch_secs() -> u64 {
+        60
+    }
+}
+
+/// Mails the sessions a rule notifies of as they start, a burst of them in a
+/// single mail. Addresses expected to log in are left out by rules ignoring
+/// them, as for every other target.
+#[derive(Clone)]
+pub struct Mailer {
+    config: Arc<SmtpConfig>,
+    queue: Sender<Notification>,
+    on_error: ErrorHandler,
+}
+
+impl Mailer {
+    const QUEUE_SIZE: usize = 256;
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn start(config: SmtpConfig, on_error: ErrorHandler) -> Result<Self> {
+        let from = config.from.parse::<Mailbox>()?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| to.parse::<Mailbox>())
+            .collect::<Result<Vec<Mailbox>, _>>()?;
+        if to.is_empty() {
+            return Err(Error::msg("smtp: no recipient"));
+        }
+
+        let transport = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+        }
+        .port(config.port)
+        .timeout(Some(Self::TIMEOUT));
+
+        let config = Arc::new(config);
+        let (queue, pending) = mpsc::channel(Self::QUEUE_SIZE);
+        tokio::spawn(Self::deliver_all(
+            transport,
+            config.clone(),
+            (from, to),
+            pending,
+            on_error.clone(),
+        ));
+
+        Ok(Mailer {
+            config,
+            queue,
+            on_error,
+        })
+    }
+
+    /// `action` is what the rules made of the session.
+    pub fn send(&self, notification: &Notification, action: RuleAction) {
+        if notification.event != NotificationEvent::SessionStarted || action != RuleAction::Notify {
+            return;
+        }
+
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(notification.clone()) {
+            (self.on_error)(format!(
+                "smtp {}: queue full, notification dropped",
+                self.config.host
+            ));
+        }
+    }
+
+    // ends once every sender is dropped
+    async fn deliver_all(
+        transport: AsyncSmtpTransportBuilder,
+        config: Arc<SmtpConfig>,
+        (from, to): (Mailbox, Vec<Mailbox>),
+        mut pending: Receiver<Notification>,
+        on_error: ErrorHandler,
+    ) {
+        while let Some(first) = pending.recv().await {
+            let mut batch = vec![first];
+            let deadline = Instant::now() + Duration::from_secs(config.batch_secs);
+            while let Ok(Some(next)) = time::timeout_at(deadline, pending.recv()).await {
+                batch.push(next);
+            }
+
+            let sent = match Self::message(&from, &to, &batch) {
+                Ok(message) => Self::send_with(&transport, &config, message).await,
+                Err(e) => {
+                    on_error(format!("smtp {}: {}", config.host, e));
+                    continue;
+                }
+            };
+            if let Err(e) = sent {
+                on_error(format!(
+                    "smtp {}: {} session(s) not mailed: {:#}",
+                    config.host,
+                    batch.len(),
+                    e
+                ));
+            }
+        }
+    }
+
+    // logs in with the password as it is now
+    async fn send_with(
+        transport: &AsyncSmtpTransportBuilder,
+        config: &SmtpConfig,
+        message: Message,
+    ) -> Result<()> {
+        let mut transport = transport.clone();
+        if let Some(username) = &config.username {
+            let password = match &config.password {
+                Some(password) => password
+                    .resolve(username)
+                    .await
+                    .context("unable to get the password")?
+                    .expose()
+                    .to_owned(),
+                None => String::new(),
+            };
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+        transport.build().send(message).await?;
+
+        Ok(())
+    }
+
+    fn message(from: &Mailbox, to: &[Mailbox], batch: &[Notification]) -> Result<Message> {
+        // only sessions are queued
+        let sessions = batch
+            .iter()
+            .filter_map(|n| Some((n, n.session.as_ref()?)))
+            .collect::<Vec<_>>();
+        let subject = match sessions[..] {
+            [(n, s)] => format!("gusta: {} logged in on {} from {}", s.user, n.device, s.ip),
+            _ => format!("gusta: {} new sessions", sessions.len()),
+        };
+        let body = sessions
+            .iter()
+            .map(|(n, s)| {
+                format!(
+                    "{} {}: {} ({}) from {}, logged in at {}\n",
+                    n.time.format("%Y-%m-%d %H:%M:%S"),
+                    n.device,
+                    s.user,
+                    s.user_type,
+                    s.ip,
+                    s.login_time
+                )
+            })
+            .collect::<String>();
+
+        let mut message = Message::builder().from(from.clone()).subject(subject);
+        for to in to {
+            message = message.to(to.clone());
+        }
+
+        Ok(message.body(body)?)
+    }
+}
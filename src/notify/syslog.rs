@@ -0,0 +1,389 @@
+use anyhow::{Context, Error, Result};
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time,
+};
+use tokio_rustls::{client::TlsStream, rustls::ServerName, TlsConnector};
+
+use super::{ErrorHandler, Notification, NotificationEvent};
+use crate::client::TlsOptions;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    #[default]
+    Udp,
+    Tcp,
+    Tls,
+}
+
+/// What goes after the RFC 5424 header.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFormat {
+    /// A sentence, the details being in the structured data.
+    #[default]
+    Text,
+    /// ArcSight Common Event Format.
+    Cef,
+}
+
+/// A `[[syslog]]` of the config.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SyslogConfig {
+    pub host: String,
+    /// 514, or 6514 over TLS, when left out.
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub transport: SyslogTransport,
+    #[serde(default)]
+    pub format: SyslogFormat,
+    /// How to trust the collector over TLS.
+    #[serde(default)]
+    pub tls: TlsOptions,
+    /// 0 to 23, 4 being security/authorization messages.
+    #[serde(default = "SyslogConfig::default_facility")]
+    pub facility: u8,
+    #[serde(default = "SyslogConfig::default_app_name")]
+    pub app_name: String,
+    /// `name@<private enterprise number>` of the structured data. The
+    /// default uses the number RFC 5612 reserves for documentation, set one
+    /// under your own for a collector keying on it.
+    #[serde(default = "SyslogConfig::default_sd_id")]
+    pub sd_id: String,
+}
+
+impl SyslogConfig {
+    fn default_facility() -> u8 {
+        4
+    }
+
+    fn default_app_name() -> String {
+        "gusta".into()
+    }
+
+    fn default_sd_id() -> String {
+        "gusta@32473".into()
+    }
+
+    pub fn port(&self) -> u16 {
+        match (self.port, self.transport) {
+            (Some(port), _) => port,
+            (None, SyslogTransport::Tls) => 6514,
+            (None, _) => 514,
+        }
+    }
+
+    /// The RFC 5424 line for `notification`, without framing.
+    pub fn format(&self, hostname: &str, notification: &Notification) -> String {
+        let (severity, cef_severity, title) = describe(notification.event);
+        let n = notification;
+        let session = n.session.as_ref();
+
+        let mut params = vec![("event", n.event.as_str()), ("device", &n.device)];
+        if let Some(s) = session {
+            params.extend([
+                ("user", s.user.as_str()),
+                ("ip", &s.ip),
+                ("user_type", &s.user_type),
+                ("login_time", &s.login_time),
+            ]);
+        }
+        if let Some(error) = &n.error {
+            params.push(("error", error));
+        }
+        let data = params
+            .iter()
+            .map(|(name, value)| format!(" {}=\"{}\"", name, escape_param(value)))
+            .collect::<String>();
+
+        let body = match self.format {
+            SyslogFormat::Text => match (n.event, session) {
+                (NotificationEvent::SessionStarted, Some(s)) => {
+                    format!("{} logged in on {} from {}", s.user, n.device, s.ip)
+                }
+                (NotificationEvent::SessionEnded, Some(s)) => {
+                    format!("{} logged out of {} from {}", s.user, n.device, s.ip)
+                }
+                _ => match &n.error {
+                    Some(error) => format!("{}: {}", n.device, error),
+                    None => format!("{}: {}", n.device, title.to_lowercase()),
+                },
+            },
+            SyslogFormat::Cef => {
+                let mut ext = vec![
+                    ("rt", n.time.timestamp_millis().to_string()),
+                    ("dvchost", n.device.clone()),
+                ];
+                if let Some(s) = session {
+                    ext.extend([
+                        ("suser", s.user.clone()),
+                        ("src", s.ip.clone()),
+                        ("cs1Label", "userType".into()),
+                        ("cs1", s.user_type.clone()),
+                        ("cs2Label", "loginTime".into()),
+                        ("cs2", s.login_time.clone()),
+                    ]);
+                }
+                if let Some(error) = &n.error {
+                    ext.push(("msg", error.clone()));
+                }
+                let ext = ext
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, escape_cef_value(value)))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+
+                format!(
+                    "CEF:0|gusta|gusta|{}|{}|{}|{}|{}",
+                    env!("CARGO_PKG_VERSION"),
+                    n.event.as_str(),
+                    title,
+                    cef_severity,
+                    ext
+                )
+            }
+        };
+
+        format!(
+            "<{}>1 {} {} {} {} {} [{}{}] {}",
+            self.facility as u32 * 8 + severity as u32,
+            n.time.to_rfc3339_opts(SecondsFormat::Millis, false),
+            header_field(hostname),
+            header_field(&self.app_name),
+            std::process::id(),
+            n.event.as_str(),
+            self.sd_id,
+            data,
+            body
+        )
+    }
+}
+
+/// Syslog severity, CEF severity and a title for `event`.
+fn describe(event: NotificationEvent) -> (u8, u8, &'static str) {
+    match event {
+        NotificationEvent::SessionStarted => (5, 5, "Session started"),
+        NotificationEvent::SessionEnded => (6, 3, "Session ended"),
+        NotificationEvent::DeviceDown => (4, 7, "Device down"),
+        NotificationEvent::DeviceConnected => (6, 3, "Device connected"),
+        NotificationEvent::DeviceDisconnected => (4, 7, "Device disconnected"),
+        NotificationEvent::Relogin => (5, 5, "Relogin"),
+    }
+}
+
+// printable ASCII without spaces, `-` standing for nothing
+fn header_field(value: &str) -> String {
+    let value = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .collect::<String>();
+    if value.is_empty() {
+        "-".into()
+    } else {
+        value
+    }
+}
+
+/// RFC 5424 SD-ID of a private enterprise: up to 32 printable characters,
+/// none of `=`, ` `, `]` or `"`, with the number after the `@`.
+fn is_sd_id(value: &str) -> bool {
+    let Some((name, number)) = value.split_once('@') else {
+        return false;
+    };
+    let name_ok = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'));
+    let number_ok = number.starts_with(|c: char| c.is_ascii_digit())
+        && number.chars().all(|c| c.is_ascii_digit() || c == '.');
+
+    value.len() <= 32 && name_ok && number_ok
+}
+
+fn escape_param(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+fn escape_cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection {
+    async fn send(&mut self, message: &str) -> std::io::Result<()> {
+        // octet counting over streams, RFC 6587 and RFC 5425
+        let framed = format!("{} {}", message.len(), message);
+        match self {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+            Connection::Tcp(stream) => {
+                stream.write_all(framed.as_bytes()).await?;
+                stream.flush().await
+            }
+            Connection::Tls(stream) => {
+                stream.write_all(framed.as_bytes()).await?;
+                stream.flush().await
+            }
+        }
+    }
+}
+
+/// Sends notifications to one collector from a queue of its own, keeping
+/// the connection open in between.
+#[derive(Clone)]
+pub struct SyslogTarget {
+    config: Arc<SyslogConfig>,
+    queue: Sender<Notification>,
+    on_error: ErrorHandler,
+}
+
+impl SyslogTarget {
+    const QUEUE_SIZE: usize = 1024;
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn start(config: SyslogConfig, on_error: ErrorHandler) -> Result<Self> {
+        if config.facility > 23 {
+            return Err(Error::msg(format!(
+                "syslog {}: facility {} is not between 0 and 23",
+                config.host, config.facility
+            )));
+        }
+        if !is_sd_id(&config.sd_id) {
+            return Err(Error::msg(format!(
+                "syslog {}: sd_id {} is not name@enterprise-number",
+                config.host, config.sd_id
+            )));
+        }
+        let tls = match config.transport {
+            SyslogTransport::Tls => Some(TlsConnector::from(Arc::new(config.tls.rustls_config()?))),
+            _ => None,
+        };
+        let hostname = hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let config = Arc::new(config);
+        let (queue, pending) = mpsc::channel(Self::QUEUE_SIZE);
+        tokio::spawn(Self::deliver_all(
+            config.clone(),
+            tls,
+            hostname,
+            pending,
+            on_error.clone(),
+        ));
+
+        Ok(SyslogTarget {
+            config,
+            queue,
+            on_error,
+        })
+    }
+
+    pub fn send(&self, notification: &Notification) {
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(notification.clone()) {
+            (self.on_error)(format!(
+                "syslog {}: queue full, notification dropped",
+                self.config.host
+            ));
+        }
+    }
+
+    // ends once every sender is dropped
+    async fn deliver_all(
+        config: Arc<SyslogConfig>,
+        tls: Option<TlsConnector>,
+        hostname: String,
+        mut pending: Receiver<Notification>,
+        on_error: ErrorHandler,
+    ) {
+        let mut connection = None;
+        while let Some(notification) = pending.recv().await {
+            let message = config.format(&hostname, &notification);
+            if let Err(e) = Self::deliver(&config, tls.as_ref(), &mut connection, &message).await {
+                on_error(format!("syslog {}: {}", config.host, e));
+            }
+        }
+    }
+
+    async fn deliver(
+        config: &SyslogConfig,
+        tls: Option<&TlsConnector>,
+        connection: &mut Option<Connection>,
+        message: &str,
+    ) -> Result<()> {
+        // a collector that went away only shows on the next write, so a
+        // reused connection gets a second chance with a new one
+        let mut reused = connection.is_some();
+        loop {
+            let conn = match connection {
+                Some(conn) => conn,
+                None => connection.insert(
+                    time::timeout(Self::TIMEOUT, Self::connect(config, tls))
+                        .await
+                        .context("timed out connecting")??,
+                ),
+            };
+            match conn.send(message).await {
+                Ok(()) => return Ok(()),
+                Err(_) if reused => {
+                    *connection = None;
+                    reused = false;
+                }
+                Err(e) => {
+                    *connection = None;
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    async fn connect(config: &SyslogConfig, tls: Option<&TlsConnector>) -> Result<Connection> {
+        let addr = (config.host.as_str(), config.port());
+
+        let connection = match config.transport {
+            SyslogTransport::Udp => {
+                let target = tokio::net::lookup_host(addr)
+                    .await?
+                    .next()
+                    .ok_or_else(|| Error::msg("no address found"))?;
+                let local: SocketAddr = match target {
+                    SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                    SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket.connect(target).await?;
+                Connection::Udp(socket)
+            }
+            SyslogTransport::Tcp => Connection::Tcp(TcpStream::connect(addr).await?),
+            SyslogTransport::Tls => {
+                let tls = tls.context("TLS not set up")?;
+                let name = ServerName::try_from(config.host.as_str())?;
+                let stream = TcpStream::connect(addr).await?;
+                Connection::Tls(Box::new(tls.connect(name, stream).await?))
+            }
+        };
+
+        Ok(connection)
+    }
+}
@@ -0,0 +1,681 @@
+use std::{
+    convert::Infallible,
+    mem,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::Local;
+use hyper::{
+    server::conn::Http, service::service_fn, Body, HeaderMap, Request, Response, StatusCode,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc,
+    time,
+};
+
+use super::{
+    render, Mailer, Notification, NotificationEvent, Notifier, SmtpConfig, SyslogConfig,
+    SyslogFormat, SyslogTarget, SyslogTransport, Webhook, WebhookConfig,
+};
+use crate::{
+    client::mock_device,
+    config::Config,
+    monitor::{DeviceChange, DeviceUser},
+    rules::{RuleAction, SessionChange},
+    secret::{Password, PasswordSource},
+    testing::{at, temp_dir},
+};
+
+/// Stands in for a chat or ticketing tool, failing the first requests it
+/// is asked to.
+struct HookServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+}
+
+impl HookServer {
+    fn start(fail_first: usize) -> Self {
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = TcpListener::from_std(listener).unwrap();
+
+        let svc_received = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = svc_received.clone();
+                tokio::spawn(async move {
+                    let svc = service_fn(move |req| handle(received.clone(), fail_first, req));
+                    let _ = Http::new().serve_connection(stream, svc).await;
+                });
+            }
+        });
+
+        HookServer { addr, received }
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/hook", self.addr)
+    }
+
+    async fn wait_for(&self, count: usize) -> Vec<(HeaderMap, String)> {
+        time::timeout(Duration::from_secs(5), async {
+            loop {
+                let received = self.received.lock().unwrap().clone();
+                if received.len() >= count {
+                    return received;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+}
+
+async fn handle(
+    received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    fail_first: usize,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+    let mut received = received.lock().unwrap();
+    received.push((headers, String::from_utf8_lossy(&body).into()));
+    let status = if received.len() <= fail_first {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::NO_CONTENT
+    };
+
+    Ok(Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap())
+}
+
+fn webhook(url: &str) -> WebhookConfig {
+    WebhookConfig {
+        url: url.into(),
+        headers: Default::default(),
+        events: vec![],
+        notified_only: false,
+        template: None,
+        retries: 3,
+        retry_delay_ms: 10,
+    }
+}
+
+fn started() -> Notification {
+    started_by("guest", "10.0.0.9")
+}
+
+fn started_by(name: &str, ip: &str) -> Notification {
+    let user = DeviceUser::new("nvr", mock_device::user(3, name, ip));
+
+    Notification::session(SessionChange::Started, &user, at(8, 30))
+}
+
+fn webhook_target(config: WebhookConfig) -> (Webhook, mpsc::UnboundedReceiver<String>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let on_error = Arc::new(move |e: String| {
+        let _ = tx.send(e);
+    });
+
+    (Webhook::start(config, on_error).unwrap(), rx)
+}
+
+#[tokio::test]
+async fn posts_json_with_custom_headers() {
+    let server = HookServer::start(0);
+    let mut config = webhook(&server.url());
+    config
+        .headers
+        .insert("Authorization".into(), "Bearer s3cret".into());
+    let (webhook, _errors) = webhook_target(config);
+
+    webhook.send(&started(), RuleAction::Notify);
+
+    let received = server.wait_for(1).await;
+    let (headers, body) = &received[0];
+    assert_eq!(headers["authorization"], "Bearer s3cret");
+    assert_eq!(headers["content-type"], "application/json");
+
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["event"], "session_started");
+    assert_eq!(json["device"], "nvr");
+    assert_eq!(json["user"], "guest");
+    assert_eq!(json["ip"], "10.0.0.9");
+    assert_eq!(json["user_type"], "operator");
+    assert_eq!(json["login_time"], "2023-11-02T08:30:00+07:00");
+}
+
+#[tokio::test]
+async fn retries_until_accepted() {
+    let server = HookServer::start(2);
+    let (webhook, mut errors) = webhook_target(webhook(&server.url()));
+
+    webhook.send(&started(), RuleAction::Notify);
+
+    let received = server.wait_for(3).await;
+    assert_eq!(received[0].1, received[2].1);
+    time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.received.lock().unwrap().len(), 3);
+    assert!(errors.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn gives_up_after_retries() {
+    let server = HookServer::start(usize::MAX);
+    let mut config = webhook(&server.url());
+    config.retries = 1;
+    let (webhook, mut errors) = webhook_target(config);
+
+    webhook.send(&started(), RuleAction::Notify);
+
+    let error = time::timeout(Duration::from_secs(5), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(error.contains("503"), "{}", error);
+    assert!(error.contains("2 attempts"), "{}", error);
+    assert_eq!(server.received.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn template_and_event_filter() {
+    let server = HookServer::start(0);
+    let mut config = webhook(&server.url());
+    config.events = vec![NotificationEvent::DeviceDown];
+    config.template = Some(r#"{"text": "{{device}} down: {{error}}{{user}}"}"#.into());
+    let (webhook, _errors) = webhook_target(config);
+
+    webhook.send(&started(), RuleAction::Notify);
+    webhook.send(
+        &Notification::device_down("nvr", "unable to reach \"nvr\"", Local::now()),
+        RuleAction::Ignore,
+    );
+
+    let received = server.wait_for(1).await;
+    assert_eq!(received.len(), 1);
+    let json: serde_json::Value = serde_json::from_str(&received[0].1).unwrap();
+    assert_eq!(json["text"], "nvr down: unable to reach \"nvr\"");
+}
+
+#[test]
+fn render_keeps_text_around_placeholders() {
+    let text = render(
+        "{{ user }} from {{ip}} at {{version}} {{nope}}{{",
+        &started(),
+    )
+    .unwrap();
+
+    assert_eq!(text, "guest from 10.0.0.9 at 1 {{");
+}
+
+/// What the SMTP sink was handed in one transaction.
+#[derive(Clone, Debug, Default)]
+struct Mail {
+    auth: Option<String>,
+    from: String,
+    to: Vec<String>,
+    data: String,
+}
+
+/// Accepts whatever it is sent, speaking just enough SMTP for lettre.
+struct SmtpSink {
+    addr: SocketAddr,
+    mails: Arc<Mutex<Vec<Mail>>>,
+}
+
+impl SmtpSink {
+    fn start() -> Self {
+        let mails = Arc::new(Mutex::new(vec![]));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = TcpListener::from_std(listener).unwrap();
+
+        let sink_mails = mails.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(stream, sink_mails.clone()));
+            }
+        });
+
+        SmtpSink { addr, mails }
+    }
+
+    async fn serve(stream: TcpStream, mails: Arc<Mutex<Vec<Mail>>>) {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut mail = Mail::default();
+
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let upper = line.to_uppercase();
+            let reply: &[u8] = if upper.starts_with("EHLO") {
+                b"250-sink\r\n250 AUTH PLAIN LOGIN\r\n"
+            } else if upper.starts_with("AUTH") {
+                mail.auth = Some(line);
+                b"235 2.7.0 accepted\r\n"
+            } else if upper.starts_with("MAIL FROM:") {
+                mail.from = line[10..].into();
+                b"250 ok\r\n"
+            } else if upper.starts_with("RCPT TO:") {
+                mail.to.push(line[8..].into());
+                b"250 ok\r\n"
+            } else if upper == "DATA" {
+                write.write_all(b"354 go ahead\r\n").await.unwrap();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    mail.data.push_str(&line);
+                    mail.data.push('\n');
+                }
+                mails.lock().unwrap().push(mem::take(&mut mail));
+                b"250 queued\r\n"
+            } else if upper == "QUIT" {
+                write.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            write.write_all(reply).await.unwrap();
+        }
+    }
+
+    async fn wait_for(&self, count: usize) -> Vec<Mail> {
+        time::timeout(Duration::from_secs(5), async {
+            loop {
+                let mails = self.mails.lock().unwrap().clone();
+                if mails.len() >= count {
+                    return mails;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+}
+
+fn smtp(port: u16) -> SmtpConfig {
+    SmtpConfig {
+        host: "127.0.0.1".into(),
+        port,
+        starttls: false,
+        username: Some("alice".into()),
+        password: Some(Password::Plain("pw".into())),
+        from: "gusta <gusta@example.com>".into(),
+        to: vec!["sec@example.com".into(), "ops@example.com".into()],
+        batch_secs: 1,
+    }
+}
+
+fn mailer(config: SmtpConfig) -> (Mailer, mpsc::UnboundedReceiver<String>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let on_error = Arc::new(move |e: String| {
+        let _ = tx.send(e);
+    });
+
+    (Mailer::start(config, on_error).unwrap(), rx)
+}
+
+#[tokio::test]
+async fn mails_burst_of_notified_sessions_once() {
+    let sink = SmtpSink::start();
+    let (mailer, _errors) = mailer(smtp(sink.addr.port()));
+
+    mailer.send(&started_by("lan", "192.168.1.20"), RuleAction::Ignore);
+    mailer.send(&started_by("guest", "10.0.0.9"), RuleAction::Notify);
+    mailer.send(&started_by("admin", "10.0.0.10"), RuleAction::Notify);
+    mailer.send(
+        &Notification::device_down("nvr", "down", Local::now()),
+        RuleAction::Notify,
+    );
+
+    let mails = sink.wait_for(1).await;
+    time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(sink.mails.lock().unwrap().len(), 1);
+
+    let mail = &mails[0];
+    // "\0alice\0pw"
+    assert_eq!(mail.auth.as_deref(), Some("AUTH PLAIN AGFsaWNlAHB3"));
+    assert_eq!(mail.from, "<gusta@example.com>");
+    assert_eq!(mail.to, ["<sec@example.com>", "<ops@example.com>"]);
+    assert!(
+        mail.data.contains("Subject: gusta: 2 new sessions"),
+        "{}",
+        mail.data
+    );
+    assert!(mail.data.contains("nvr: guest (operator) from 10.0.0.9"));
+    assert!(mail.data.contains("nvr: admin (operator) from 10.0.0.10"));
+    assert!(!mail.data.contains("lan"));
+}
+
+#[tokio::test]
+async fn single_session_names_it() {
+    let sink = SmtpSink::start();
+    let mut config = smtp(sink.addr.port());
+    config.batch_secs = 0;
+    let (mailer, _errors) = mailer(config);
+
+    mailer.send(&started(), RuleAction::Notify);
+
+    let mails = sink.wait_for(1).await;
+    assert!(
+        mails[0]
+            .data
+            .contains("Subject: gusta: guest logged in on nvr from 10.0.0.9"),
+        "{}",
+        mails[0].data
+    );
+}
+
+#[tokio::test]
+async fn unreachable_server_is_reported() {
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let mut config = smtp(port);
+    config.batch_secs = 0;
+    let (mailer, mut errors) = mailer(config);
+
+    mailer.send(&started(), RuleAction::Notify);
+
+    let error = time::timeout(Duration::from_secs(5), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(error.contains("1 session(s) not mailed"), "{}", error);
+}
+
+#[tokio::test]
+async fn password_is_read_for_every_mail() {
+    let sink = SmtpSink::start();
+    let dir = temp_dir();
+    let password_file = dir.path().join("password");
+    std::fs::write(&password_file, "pw\n").unwrap();
+    let mut config = smtp(sink.addr.port());
+    config.batch_secs = 0;
+    config.password = Some(Password::From(PasswordSource::File(password_file.clone())));
+    let (mailer, mut errors) = mailer(config);
+
+    mailer.send(&started(), RuleAction::Notify);
+    let mails = sink.wait_for(1).await;
+    assert_eq!(mails[0].auth.as_deref(), Some("AUTH PLAIN AGFsaWNlAHB3"));
+
+    std::fs::remove_file(&password_file).unwrap();
+    mailer.send(&started(), RuleAction::Notify);
+    let error = time::timeout(Duration::from_secs(5), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(error.contains("unable to get the password"), "{}", error);
+}
+
+fn syslog(port: u16, transport: SyslogTransport, format: SyslogFormat) -> SyslogConfig {
+    SyslogConfig {
+        host: "127.0.0.1".into(),
+        port: Some(port),
+        transport,
+        format,
+        tls: Default::default(),
+        facility: 4,
+        app_name: "gusta".into(),
+        sd_id: "gusta@32473".into(),
+    }
+}
+
+fn syslog_target(config: SyslogConfig) -> SyslogTarget {
+    SyslogTarget::start(config, Arc::new(|_| {})).unwrap()
+}
+
+async fn tcp_collector() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+// one octet counted message
+async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> String {
+    let mut len = String::new();
+    loop {
+        match stream.read_u8().await.unwrap() {
+            b' ' => break,
+            digit => len.push(digit as char),
+        }
+    }
+    let mut message = vec![0; len.parse().unwrap()];
+    stream.read_exact(&mut message).await.unwrap();
+
+    String::from_utf8(message).unwrap()
+}
+
+#[tokio::test]
+async fn syslog_over_udp_carries_structured_data() {
+    let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = collector.local_addr().unwrap().port();
+    let target = syslog_target(syslog(port, SyslogTransport::Udp, SyslogFormat::Text));
+
+    target.send(&started());
+
+    let mut buf = [0; 2048];
+    let len = time::timeout(Duration::from_secs(5), collector.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    let message = String::from_utf8_lossy(&buf[..len]);
+
+    // auth facility, notice
+    assert!(
+        message.starts_with("<37>1 2023-11-02T08:30:00.000"),
+        "{}",
+        message
+    );
+    assert!(message.contains(" gusta "));
+    assert!(message.contains(
+        " session_started [gusta@32473 event=\"session_started\" device=\"nvr\" \
+         user=\"guest\" ip=\"10.0.0.9\" user_type=\"operator\""
+    ));
+    assert!(message.ends_with("] guest logged in on nvr from 10.0.0.9"));
+}
+
+#[tokio::test]
+async fn syslog_over_tcp_frames_cef() {
+    let (collector, port) = tcp_collector().await;
+    let target = syslog_target(syslog(port, SyslogTransport::Tcp, SyslogFormat::Cef));
+
+    target.send(&started());
+    target.send(&Notification::device_disconnected(
+        "nvr",
+        "session lost",
+        Local::now(),
+    ));
+
+    let (mut stream, _) = collector.accept().await.unwrap();
+    let started = read_frame(&mut stream).await;
+    let disconnected = read_frame(&mut stream).await;
+
+    assert!(started.contains(&format!(
+        "CEF:0|gusta|gusta|{}|session_started|Session started|5|rt=",
+        env!("CARGO_PKG_VERSION")
+    )));
+    assert!(
+        started.contains(" dvchost=nvr suser=guest src=10.0.0.9 cs1Label=userType cs1=operator")
+    );
+    // auth facility, warning
+    assert!(disconnected.starts_with("<36>1 "), "{}", disconnected);
+    assert!(disconnected.contains("|device_disconnected|Device disconnected|7|"));
+    assert!(disconnected.ends_with(" msg=session lost"));
+}
+
+#[tokio::test]
+async fn syslog_over_tls_trusts_ca_file() {
+    let (collector, port) = tcp_collector().await;
+    let mut config = syslog(port, SyslogTransport::Tls, SyslogFormat::Text);
+    config.tls.ca_file = Some(mock_device::CA_FILE.into());
+    let target = syslog_target(config);
+
+    target.send(&Notification::device_connected("nvr", Local::now()));
+
+    let (stream, _) = collector.accept().await.unwrap();
+    let mut stream = mock_device::tls_acceptor().accept(stream).await.unwrap();
+    let message = read_frame(&mut stream).await;
+
+    assert!(
+        message.contains(" device_connected [gusta@32473 "),
+        "{}",
+        message
+    );
+    assert!(message.ends_with("] nvr: device connected"));
+}
+
+#[tokio::test]
+async fn syslog_sd_id_is_configurable() {
+    let mut config = syslog(514, SyslogTransport::Udp, SyslogFormat::Text);
+    config.sd_id = "audit@99999.1".into();
+    assert!(config
+        .format("myhost", &started())
+        .contains(" session_started [audit@99999.1 event="));
+    assert!(SyslogTarget::start(config.clone(), Arc::new(|_| {})).is_ok());
+
+    for bad in [
+        "gusta",
+        "gusta@",
+        "gu sta@32473",
+        "gusta@pen",
+        "gusta]@32473",
+    ] {
+        config.sd_id = bad.into();
+        assert!(SyslogTarget::start(config.clone(), Arc::new(|_| {})).is_err());
+    }
+}
+
+#[test]
+fn syslog_escapes_values() {
+    let mut notification = started_by("a\"b]", "10.0.0.9");
+    notification.error = Some("x=1\ny".into());
+    let text = syslog(514, SyslogTransport::Udp, SyslogFormat::Text);
+    let cef = syslog(514, SyslogTransport::Udp, SyslogFormat::Cef);
+
+    let message = text.format("my host", &notification);
+    assert!(message.contains(" myhost gusta "), "{}", message);
+    assert!(message.contains(" user=\"a\\\"b\\]\" "), "{}", message);
+
+    let message = cef.format("my host", &notification);
+    assert!(message.contains(" suser=a\"b] "), "{}", message);
+    assert!(message.ends_with(" msg=x\\=1\\ny"), "{}", message);
+}
+
+#[tokio::test]
+async fn notifier_routes_changes_by_rules() {
+    let server = HookServer::start(0);
+    let notified = HookServer::start(0);
+    let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mail = SmtpSink::start();
+    let config: Config = toml::from_str(&format!(
+        r#"
+        [[device]]
+        endpoint = "http://10.0.0.2"
+        username = "admin"
+        password = "secret"
+
+        [[rule]]
+        users = ["guest"]
+        action = "notify"
+
+        [[webhook]]
+        url = "{}"
+
+        [[webhook]]
+        url = "{}"
+        notified_only = true
+
+        [[syslog]]
+        host = "127.0.0.1"
+        port = {}
+
+        [smtp]
+        host = "127.0.0.1"
+        port = {}
+        starttls = false
+        from = "gusta@example.com"
+        to = ["sec@example.com"]
+        batch_secs = 0
+        "#,
+        server.url(),
+        notified.url(),
+        collector.local_addr().unwrap().port(),
+        mail.addr.port()
+    ))
+    .unwrap();
+    let notifier = Notifier::start(&config, Arc::new(|_| {})).unwrap();
+    let session = |name: &str, initial| DeviceChange::Session {
+        change: SessionChange::Started,
+        user: DeviceUser::new("nvr", mock_device::user(3, name, "10.0.0.9")),
+        initial,
+    };
+    let rules = config.rules();
+    let now = Local::now();
+
+    assert_eq!(
+        notifier.change(&session("admin", false), &rules, now),
+        RuleAction::Sound
+    );
+    assert_eq!(
+        notifier.change(&session("guest", false), &rules, now),
+        RuleAction::Notify
+    );
+    notifier.change(&session("guest", true), &rules, now);
+    let relogin = DeviceChange::Relogin {
+        device: "nvr".into(),
+    };
+    notifier.change(&relogin, &rules, now);
+
+    let users = |received: Vec<(_, String)>| {
+        received
+            .iter()
+            .map(|(_, body)| {
+                let json = serde_json::from_str::<serde_json::Value>(body).unwrap();
+                json.get("user").unwrap_or(&json["event"]).clone()
+            })
+            .collect::<Vec<_>>()
+    };
+    // every change but the sessions found at startup
+    assert_eq!(
+        users(server.wait_for(3).await),
+        ["admin", "guest", "relogin"]
+    );
+    // only the sessions a rule notifies of
+    assert_eq!(users(notified.wait_for(2).await), ["guest", "relogin"]);
+    let mut buf = [0; 2048];
+    for expected in [" user=\"admin\" ", " user=\"guest\" ", " relogin "] {
+        let len = time::timeout(Duration::from_secs(5), collector.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).contains(expected));
+    }
+    // the sessions a rule notifies of, as they start
+    let mails = mail.wait_for(1).await;
+    time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(mail.mails.lock().unwrap().len(), 1);
+    assert!(
+        mails[0].data.contains("nvr: guest (operator)"),
+        "{}",
+        mails[0].data
+    );
+}
@@ -0,0 +1,194 @@
+use anyhow::{Error, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time,
+};
+
+use super::{ErrorHandler, Notification, NotificationEvent};
+use crate::rules::RuleAction;
+
+/// A `[[webhook]]` of the config.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Sent with every request, e.g. `Authorization`.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Events posted, every one when left empty.
+    #[serde(default)]
+    pub events: Vec<NotificationEvent>,
+    /// Sessions posted only when a rule notifies of them, rather than
+    /// every one.
+    #[serde(default)]
+    pub notified_only: bool,
+    /// Body with `{{field}}` placeholders for the fields of the default JSON
+    /// payload. Values are JSON escaped, so string ones go inside quotes.
+    pub template: Option<String>,
+    /// Attempts after the first one failed.
+    #[serde(default = "WebhookConfig::default_retries")]
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every next one.
+    #[serde(default = "WebhookConfig::default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+}
+
+impl WebhookConfig {
+    fn default_retries() -> u32 {
+        3
+    }
+
+    fn default_retry_delay_ms() -> u64 {
+        1000
+    }
+}
+
+/// Posts notifications to one URL from a queue of its own, so a slow or
+/// failing endpoint holds up neither gusta nor the other targets. The queue
+/// is in memory, so it rides out an outage only while gusta runs: what is
+/// still in it on quit is lost. On reload the old queue is still drained.
+#[derive(Clone)]
+pub struct Webhook {
+    config: Arc<WebhookConfig>,
+    queue: Sender<Notification>,
+    on_error: ErrorHandler,
+}
+
+impl Webhook {
+    const QUEUE_SIZE: usize = 256;
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn start(config: WebhookConfig, on_error: ErrorHandler) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        for (name, value) in &config.headers {
+            headers.insert(
+                HeaderName::try_from(name.as_str())?,
+                HeaderValue::try_from(value.as_str())?,
+            );
+        }
+        let http = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .default_headers(headers)
+            .build()?;
+
+        let config = Arc::new(config);
+        let (queue, pending) = mpsc::channel(Self::QUEUE_SIZE);
+        tokio::spawn(Self::deliver_all(
+            http,
+            config.clone(),
+            pending,
+            on_error.clone(),
+        ));
+
+        Ok(Webhook {
+            config,
+            queue,
+            on_error,
+        })
+    }
+
+    /// `action` is what the rules made of a session, ignored for anything
+    /// else.
+    pub fn send(&self, notification: &Notification, action: RuleAction) {
+        let events = &self.config.events;
+        if !events.is_empty() && !events.contains(&notification.event) {
+            return;
+        }
+        let is_session = notification.session.is_some();
+        if self.config.notified_only && is_session && action != RuleAction::Notify {
+            return;
+        }
+
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(notification.clone()) {
+            (self.on_error)(format!(
+                "webhook {}: queue full, notification dropped",
+                self.config.url
+            ));
+        }
+    }
+
+    // ends once every sender is dropped
+    async fn deliver_all(
+        http: Client,
+        config: Arc<WebhookConfig>,
+        mut pending: Receiver<Notification>,
+        on_error: ErrorHandler,
+    ) {
+        while let Some(notification) = pending.recv().await {
+            if let Err(e) = Self::deliver(&http, &config, &notification).await {
+                on_error(format!("webhook {}: {}", config.url, e));
+            }
+        }
+    }
+
+    async fn deliver(
+        http: &Client,
+        config: &WebhookConfig,
+        notification: &Notification,
+    ) -> Result<()> {
+        let body = match &config.template {
+            Some(template) => render(template, notification)?,
+            None => serde_json::to_string(notification)?,
+        };
+
+        let mut attempt = 0;
+        loop {
+            let error = match http.post(&config.url).body(body.clone()).send().await {
+                Ok(res) if res.status().is_success() => return Ok(()),
+                Ok(res) => format!("answered {}", res.status()),
+                Err(e) => e.to_string(),
+            };
+            if attempt == config.retries {
+                return Err(Error::msg(format!(
+                    "{}, gave up after {} attempts",
+                    error,
+                    attempt + 1
+                )));
+            }
+
+            let delay = Duration::from_millis(config.retry_delay_ms);
+            time::sleep(delay.saturating_mul(2u32.saturating_pow(attempt))).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Fills the `{{field}}` placeholders of `template`, the ones the
+/// notification has no value for with nothing.
+pub fn render(template: &str, notification: &Notification) -> Result<String> {
+    let fields = match serde_json::to_value(notification)? {
+        Value::Object(fields) => fields,
+        _ => Default::default(),
+    };
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+
+        let name = rest[start + 2..start + len].trim();
+        match fields.get(name) {
+            // escaped, without the quotes around
+            Some(Value::String(s)) => {
+                let quoted = serde_json::to_string(s)?;
+                out.push_str(&quoted[1..quoted.len() - 1]);
+            }
+            Some(value) => out.push_str(&value.to_string()),
+            None => {}
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
@@ -0,0 +1,238 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{fmt, future::Future, io::Write, str::FromStr, time::Duration};
+use tokio::time;
+
+use crate::{config::DeviceConfig, monitor::DeviceUser};
+
+/// How `once` prints the users.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnceFormat {
+    Table,
+    Json,
+}
+
+impl OnceFormat {
+    pub const ALL: [OnceFormat; 2] = [OnceFormat::Table, OnceFormat::Json];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            OnceFormat::Table => "table",
+            OnceFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for OnceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown format {}, expected table or json", s))
+    }
+}
+
+impl fmt::Display for OnceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Serialize)]
+struct UserRow<'a> {
+    device: &'a str,
+    user: &'a str,
+    user_type: &'a str,
+    ip: &'a str,
+    login_time: &'a str,
+}
+
+impl<'a> From<&'a DeviceUser> for UserRow<'a> {
+    fn from(u: &'a DeviceUser) -> Self {
+        UserRow {
+            device: &u.device,
+            user: &u.user.name,
+            user_type: &u.user.user_type,
+            ip: &u.user.client_address.ip_address,
+            login_time: &u.user.login_time,
+        }
+    }
+}
+
+// a device that neither answers nor refuses would hold a script for minutes
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Logs into every device once, without retrying, returning how it went
+/// in the order of `devices`.
+pub async fn check(devices: &[DeviceConfig]) -> Vec<(String, Result<()>)> {
+    on_each(devices, |device| async move {
+        let mut client = device.client().await?;
+        client.login().await?;
+        client.logout().await;
+        Ok(())
+    })
+    .await
+}
+
+/// The users online on every device, but for the account gusta logs in
+/// with.
+pub async fn online_users(devices: &[DeviceConfig]) -> Vec<(String, Result<Vec<DeviceUser>>)> {
+    on_each(devices, |device| async move {
+        let mut client = device.client().await?;
+        client.login().await?;
+        let online = client.fetch_online_users().await;
+        client.logout().await;
+
+        Ok(online?
+            .users
+            .into_iter()
+            .filter(|u| u.name != device.username)
+            .map(|u| DeviceUser::new(&device.name, u))
+            .collect())
+    })
+    .await
+}
+
+// all devices at once, so the slowest sets how long it takes
+async fn on_each<T, F, Fut>(devices: &[DeviceConfig], probe: F) -> Vec<(String, Result<T>)>
+where
+    T: Send + 'static,
+    F: Fn(DeviceConfig) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    let tasks = devices
+        .iter()
+        .map(|d| {
+            let probe = time::timeout(TIMEOUT, probe(d.clone()));
+            (d.name.clone(), tokio::spawn(probe))
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (name, task) in tasks {
+        let result = match task.await {
+            Ok(Ok(result)) => result,
+            Ok(Err(elapsed)) => Err(elapsed.into()),
+            Err(e) => Err(e.into()),
+        };
+        results.push((name, result));
+    }
+
+    results
+}
+
+pub fn write_users(users: &[DeviceUser], format: OnceFormat, out: &mut impl Write) -> Result<()> {
+    let rows = users.iter().map(UserRow::from).collect::<Vec<UserRow>>();
+    match format {
+        OnceFormat::Table => write_table(&rows, out)?,
+        OnceFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &rows)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn write_table(rows: &[UserRow], out: &mut impl Write) -> Result<()> {
+    let header = ["DEVICE", "USER", "TYPE", "IP", "LOGIN"];
+    let lines = rows
+        .iter()
+        .map(|r| [r.device, r.user, r.user_type, r.ip, r.login_time])
+        .collect::<Vec<[&str; 5]>>();
+
+    let mut widths = header.map(|h| h.chars().count());
+    for line in &lines {
+        for (width, field) in widths.iter_mut().zip(line) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    for line in std::iter::once(&header).chain(&lines) {
+        let fields = line
+            .iter()
+            .zip(widths)
+            .map(|(field, width)| format!("{:<width$}", field, width = width))
+            .collect::<Vec<String>>();
+        writeln!(out, "{}", fields.join("  ").trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::Exit,
+        client::mock_device::{self, MockDevice, PASSWORD, USERNAME},
+    };
+    use serde_json::Value;
+
+    fn device(name: &str, endpoint: &str, password: &str) -> DeviceConfig {
+        toml::from_str(&format!(
+            r#"
+            name = "{}"
+            endpoint = "{}"
+            username = "{}"
+            password = "{}"
+            "#,
+            name, endpoint, USERNAME, password
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn check_tells_failures_apart() {
+        let device_a = MockDevice::start();
+        let device_b = MockDevice::start();
+        let devices = [
+            device("a", &device_a.endpoint(), PASSWORD),
+            device("b", &device_b.endpoint(), "wrong"),
+            device("c", "http://127.0.0.1:1", PASSWORD),
+        ];
+
+        let results = check(&devices).await;
+
+        let names = results.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(results[0].1.is_ok());
+        assert_eq!(Exit::of(results[1].1.as_ref().unwrap_err()), Exit::Auth);
+        assert_eq!(
+            Exit::of(results[2].1.as_ref().unwrap_err()),
+            Exit::Unreachable
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_online_users_but_own() {
+        let mock = MockDevice::start();
+        mock.set_users(vec![
+            mock_device::user(1, USERNAME, "10.0.0.2"),
+            mock_device::user(2, "guard", "10.0.0.77"),
+        ]);
+
+        let mut results = online_users(&[device("nvr", &mock.endpoint(), PASSWORD)]).await;
+        let users = results.remove(0).1.unwrap();
+        assert_eq!(users.len(), 1);
+
+        let mut table = vec![];
+        write_users(&users, OnceFormat::Table, &mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("DEVICE  USER   TYPE"));
+        assert!(lines[1].starts_with("nvr     guard"));
+        assert!(lines[1].contains("10.0.0.77"));
+
+        let mut json = vec![];
+        write_users(&users, OnceFormat::Json, &mut json).unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[0]["device"], "nvr");
+        assert_eq!(json[0]["user"], "guard");
+        assert_eq!(json[0]["ip"], "10.0.0.77");
+    }
+}
@@ -0,0 +1,338 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::{fmt, net::IpAddr};
+
+use crate::{client::Hashable, monitor::DeviceUser};
+
+/// What to do about a session a rule matched.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Ignore,
+    #[default]
+    Sound,
+    /// Sound, and tell whoever is watching.
+    Notify,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionChange {
+    Started,
+    Ended,
+}
+
+impl fmt::Display for SessionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionChange::Started => write!(f, "logged in"),
+            SessionChange::Ended => write!(f, "logged out"),
+        }
+    }
+}
+
+/// Which session changes a rule looks at.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleOn {
+    Start,
+    End,
+    #[default]
+    Any,
+}
+
+/// A `[[rule]]` of the config. Every criterion left empty matches any
+/// session, the first rule matching decides.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rule {
+    #[serde(default)]
+    pub name: String,
+    /// Devices by name.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// CIDR ranges or single addresses the client is in.
+    #[serde(default)]
+    pub ips: Vec<IpRange>,
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// `administrator`, `operator`, `viewer`...
+    #[serde(default)]
+    pub user_types: Vec<String>,
+    /// Local time windows such as `08:00-18:00`, `22:00-06:00` going past
+    /// midnight.
+    #[serde(default)]
+    pub hours: Vec<HourWindow>,
+    /// `mon`, `tue`... when the hours apply.
+    #[serde(default)]
+    pub days: Vec<Day>,
+    #[serde(default)]
+    pub on: RuleOn,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    pub fn matches(&self, user: &DeviceUser, change: SessionChange, at: DateTime<Local>) -> bool {
+        let on = match self.on {
+            RuleOn::Start => change == SessionChange::Started,
+            RuleOn::End => change == SessionChange::Ended,
+            RuleOn::Any => true,
+        };
+        let ip = user.user.client_address.ip_address.parse::<IpAddr>().ok();
+        let time = at.time();
+
+        on && (self.devices.is_empty() || self.devices.contains(&user.device))
+            && (self.ips.is_empty() || ip.is_some_and(|ip| self.ips.iter().any(|r| r.contains(ip))))
+            && (self.users.is_empty() || self.users.contains(&user.user.name))
+            && (self.user_types.is_empty()
+                || self
+                    .user_types
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(&user.user.user_type)))
+            && (self.hours.is_empty() || self.hours.iter().any(|h| h.contains(time)))
+            && (self.days.is_empty() || self.days.iter().any(|d| d.0 == at.weekday()))
+    }
+}
+
+/// The rules of the config, in order.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    default_action: RuleAction,
+}
+
+impl Rules {
+    pub fn new(rules: Vec<Rule>, default_action: RuleAction) -> Self {
+        Rules {
+            rules,
+            default_action,
+        }
+    }
+
+    /// The action of the first rule matching, with its name.
+    pub fn evaluate(
+        &self,
+        user: &DeviceUser,
+        change: SessionChange,
+        at: DateTime<Local>,
+    ) -> (RuleAction, Option<&str>) {
+        self.rules
+            .iter()
+            .find(|r| r.matches(user, change, at))
+            .map(|r| (r.action, Some(r.name.as_str())))
+            .unwrap_or((self.default_action, None))
+    }
+}
+
+/// Sessions in `after` but not `before`, then the other way round.
+pub fn session_changes(
+    before: &[DeviceUser],
+    after: &[DeviceUser],
+) -> Vec<(SessionChange, DeviceUser)> {
+    // users compare by name, a session is the whole of it
+    let keys = |users: &[DeviceUser]| users.iter().map(|u| u.hash_value()).collect::<Vec<u64>>();
+    let (before_keys, after_keys) = (keys(before), keys(after));
+
+    let started = after
+        .iter()
+        .filter(|u| !before_keys.contains(&u.hash_value()))
+        .map(|u| (SessionChange::Started, u.clone()));
+    let ended = before
+        .iter()
+        .filter(|u| !after_keys.contains(&u.hash_value()))
+        .map(|u| (SessionChange::Ended, u.clone()));
+
+    started.chain(ended).collect()
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct IpRange(IpNet);
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.contains(&ip)
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse::<IpNet>()
+            .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+            .map(IpRange)
+            .map_err(|_| format!("{} is not an address or CIDR range", value))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct HourWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl HourWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for HourWindow {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").ok();
+        value
+            .split_once('-')
+            .and_then(|(start, end)| {
+                Some(HourWindow {
+                    start: parse(start)?,
+                    end: parse(end)?,
+                })
+            })
+            .ok_or_else(|| format!("{} is not a HH:MM-HH:MM window", value))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Day(Weekday);
+
+impl TryFrom<String> for Day {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse::<Weekday>()
+            .map(Day)
+            .map_err(|_| format!("{} is not a day of the week", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::mock_device, testing::at};
+
+    fn session(name: &str, ip: &str) -> DeviceUser {
+        DeviceUser::new("nvr", mock_device::user(1, name, ip))
+    }
+
+    fn rules(toml: &str) -> Rules {
+        #[derive(Deserialize)]
+        struct Conf {
+            rule: Vec<Rule>,
+        }
+        let conf: Conf = toml::from_str(toml).unwrap();
+        Rules::new(conf.rule, RuleAction::Notify)
+    }
+
+    #[test]
+    fn office_hours_from_the_lan_are_ignored() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            name = "office"
+            ips = ["192.168.1.0/24", "10.0.0.5"]
+            hours = ["08:00-18:00"]
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            action = "ignore"
+            "#,
+        );
+        let lan = session("admin", "192.168.1.20");
+        let started = SessionChange::Started;
+
+        assert_eq!(
+            rules.evaluate(&lan, started, at(9, 0)),
+            (RuleAction::Ignore, Some("office"))
+        );
+        assert_eq!(
+            rules
+                .evaluate(&session("admin", "10.0.0.5"), started, at(9, 0))
+                .0,
+            RuleAction::Ignore
+        );
+        assert_eq!(
+            rules.evaluate(&lan, started, at(18, 0)).0,
+            RuleAction::Notify
+        );
+        assert_eq!(
+            rules.evaluate(&session("admin", "10.0.0.6"), started, at(9, 0)),
+            (RuleAction::Notify, None)
+        );
+        // a saturday
+        let weekend = at(9, 0) + chrono::Duration::days(2);
+        assert_eq!(rules.evaluate(&lan, started, weekend).0, RuleAction::Notify);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            users = ["guest"]
+            on = "end"
+            action = "ignore"
+
+            [[rule]]
+            user_types = ["operator"]
+            action = "sound"
+            "#,
+        );
+        let guest = session("guest", "10.0.0.9");
+
+        assert_eq!(
+            rules.evaluate(&guest, SessionChange::Ended, at(9, 0)).0,
+            RuleAction::Ignore
+        );
+        assert_eq!(
+            rules.evaluate(&guest, SessionChange::Started, at(9, 0)).0,
+            RuleAction::Sound
+        );
+    }
+
+    #[test]
+    fn night_window_wraps_midnight() {
+        let night = HourWindow::try_from("22:00-06:00".to_string()).unwrap();
+
+        assert!(night.contains(at(23, 30).time()));
+        assert!(night.contains(at(5, 59).time()));
+        assert!(!night.contains(at(6, 0).time()));
+        assert!(HourWindow::try_from("22:00".to_string()).is_err());
+    }
+
+    #[test]
+    fn bad_range_is_rejected() {
+        assert!(IpRange::try_from("192.168.1.0/33".to_string()).is_err());
+        assert!(IpRange::try_from("fe80::/10".to_string()).is_ok());
+    }
+
+    #[test]
+    fn changes_are_started_then_ended() {
+        let (a, b, c) = (
+            session("a", "10.0.0.1"),
+            session("b", "10.0.0.2"),
+            session("c", "10.0.0.3"),
+        );
+
+        // same user, another address
+        let b2 = session("b", "10.0.0.4");
+
+        let changes = session_changes(&[a.clone(), b.clone()], &[b, b2.clone(), c.clone()]);
+
+        assert_eq!(
+            changes,
+            vec![
+                (SessionChange::Started, b2),
+                (SessionChange::Started, c),
+                (SessionChange::Ended, a)
+            ]
+        );
+    }
+}
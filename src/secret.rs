@@ -0,0 +1,227 @@
+use anyhow::{Context, Error, Result};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{env, fmt, path::PathBuf, process::Stdio, time::Duration};
+use tokio::{fs, process::Command, task, time};
+use zeroize::Zeroizing;
+
+use crate::client::SecretString;
+
+/// The `password` of a device or of the mail server, either the password
+/// itself or where to find it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Password {
+    Plain(SecretString),
+    From(PasswordSource),
+}
+
+// not untagged, which would answer a typo in the table with "did not match
+// any variant" rather than name the key
+impl<'de> Deserialize<'de> for Password {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PasswordVisitor;
+
+        impl<'de> Visitor<'de> for PasswordVisitor {
+            type Value = Password;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a password or a table such as { env = \"NVR_PASSWORD\" }")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Password, E> {
+                Ok(Password::Plain(value.into()))
+            }
+
+            fn visit_string<E: de::Error>(self, value: String) -> Result<Password, E> {
+                Ok(Password::Plain(value.into()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Password, A::Error> {
+                PasswordSource::deserialize(MapAccessDeserializer::new(map)).map(Password::From)
+            }
+        }
+
+        deserializer.deserialize_any(PasswordVisitor)
+    }
+}
+
+/// `password = { env = "NVR_PASSWORD" }` and the like.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordSource {
+    /// An environment variable.
+    Env(String),
+    /// A file holding nothing but the password, as systemd and Docker
+    /// credentials are. A trailing newline is not part of it.
+    File(PathBuf),
+    /// A program and its arguments, run without a shell, printing the
+    /// password on its first line, e.g. `["pass", "show", "nvr"]`. Killed
+    /// when it takes longer than 30 seconds.
+    Command(Vec<String>),
+    /// The Secret Service entry of this service for the device username.
+    Keyring(String),
+}
+
+impl Password {
+    /// Reads the password from its source, every time it is asked for.
+    pub async fn resolve(&self, username: &str) -> Result<SecretString> {
+        match self {
+            Password::Plain(password) => Ok(password.clone()),
+            Password::From(source) => source.read(username).await,
+        }
+    }
+}
+
+impl PasswordSource {
+    // a command waiting on a prompt nobody sees would hold up the device
+    const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+    async fn run(command: &[String], timeout: Duration) -> Result<SecretString> {
+        let [program, args @ ..] = command else {
+            return Err(Error::msg("password command is empty"));
+        };
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("unable to run {}", program))?;
+        let output = time::timeout(timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                Error::msg(format!(
+                    "{} did not finish within {} seconds, killed it",
+                    program,
+                    timeout.as_secs_f32()
+                ))
+            })?
+            .with_context(|| format!("unable to run {}", program))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::msg(format!(
+                "{} failed with {}: {}",
+                program,
+                output.status,
+                stderr.trim()
+            )));
+        }
+
+        let stdout = String::from_utf8(output.stdout)
+            .map(Zeroizing::new)
+            .with_context(|| format!("{} printed something else than text", program))?;
+        Ok(stdout.lines().next().unwrap_or_default().into())
+    }
+
+    async fn read(&self, username: &str) -> Result<SecretString> {
+        match self {
+            PasswordSource::Env(var) => env::var(var)
+                .map(SecretString::from)
+                .with_context(|| format!("environment variable {} not set", var)),
+            PasswordSource::File(path) => {
+                let password = fs::read_to_string(path)
+                    .await
+                    .map(Zeroizing::new)
+                    .with_context(|| format!("unable to read {}", path.display()))?;
+                Ok(password.trim_end_matches(['\r', '\n']).into())
+            }
+            PasswordSource::Command(command) => Self::run(command, Self::COMMAND_TIMEOUT).await,
+            PasswordSource::Keyring(service) => {
+                // the Secret Service is asked over a blocking D-Bus call
+                let (service, username) = (service.clone(), username.to_owned());
+                task::spawn_blocking(move || {
+                    keyring::Entry::new(&service, &username)
+                        .and_then(|entry| entry.get_password())
+                        .map(SecretString::from)
+                        .with_context(|| {
+                            format!("no password for {} in keyring {}", username, service)
+                        })
+                })
+                .await?
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[derive(Deserialize)]
+    struct Device {
+        password: Password,
+    }
+
+    fn password(toml: &str) -> Password {
+        toml::from_str::<Device>(toml).unwrap().password
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert!(
+            matches!(password(r#"password = "secret""#), Password::Plain(p) if p.expose() == "secret")
+        );
+        assert!(matches!(
+            password(r#"password = { env = "NVR_PASSWORD" }"#),
+            Password::From(PasswordSource::Env(var)) if var == "NVR_PASSWORD"
+        ));
+        assert!(matches!(
+            password(r#"password = { command = ["pass", "show", "nvr"] }"#),
+            Password::From(PasswordSource::Command(c)) if c == ["pass", "show", "nvr"]
+        ));
+        let typo = toml::from_str::<Device>(r#"password = { evn = "NVR_PASSWORD" }"#)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(typo.contains("unknown variant `evn`"), "{}", typo);
+    }
+
+    #[tokio::test]
+    async fn resolves_env_file_and_command() {
+        env::set_var("GUSTA_TEST_PASSWORD", "from env");
+        let from_env = password(r#"password = { env = "GUSTA_TEST_PASSWORD" }"#);
+        assert_eq!(
+            from_env.resolve("admin").await.unwrap().expose(),
+            "from env"
+        );
+        let unset = password(r#"password = { env = "GUSTA_TEST_UNSET" }"#);
+        assert!(unset.resolve("admin").await.is_err());
+
+        let dir = temp_dir();
+        let file = dir.path().join("password");
+        std::fs::write(&file, "from file\n").unwrap();
+        let from_file = password(&format!("password = {{ file = '{}' }}", file.display()));
+        assert_eq!(
+            from_file.resolve("admin").await.unwrap().expose(),
+            "from file"
+        );
+
+        let from_command =
+            password(r#"password = { command = ["sh", "-c", "echo 'from command'; echo meta"] }"#);
+        assert_eq!(
+            from_command.resolve("admin").await.unwrap().expose(),
+            "from command"
+        );
+        let failing = password(r#"password = { command = ["sh", "-c", "echo nope >&2; exit 2"] }"#);
+        let error = failing.resolve("admin").await.unwrap_err().to_string();
+        assert!(error.contains("nope"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn kills_command_taking_too_long() {
+        let started = std::time::Instant::now();
+        let command = ["sleep".to_string(), "10".to_string()];
+
+        let error = PasswordSource::run(&command, Duration::from_millis(100))
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("did not finish"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    client::{ClientAddress, Hashable, OnlineUser},
+    monitor::DeviceUser,
+};
+
+/// A session as seen by gusta, one line of the history file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    pub device: String,
+    pub user: String,
+    pub user_type: String,
+    pub ip: String,
+    /// As reported by the device, in its own time zone.
+    pub login_time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<PathBuf>,
+}
+
+impl SessionRecord {
+    fn new(user: &DeviceUser, now: DateTime<Local>) -> Self {
+        SessionRecord {
+            first_seen: now,
+            last_seen: now,
+            device: user.device.clone(),
+            user: user.user.name.clone(),
+            user_type: user.user.user_type.clone(),
+            ip: user.user.client_address.ip_address.clone(),
+            login_time: user.user.login_time.clone(),
+            snapshot: user.snapshot.clone(),
+        }
+    }
+
+    pub fn device_user(&self) -> DeviceUser {
+        let mut user = DeviceUser::new(
+            &self.device,
+            OnlineUser {
+                // ids are reused by the device once a session ends
+                id: 0,
+                name: self.user.clone(),
+                user_type: self.user_type.clone(),
+                login_time: self.login_time.clone(),
+                client_address: ClientAddress {
+                    ip_address: self.ip.clone(),
+                },
+            },
+        );
+        user.snapshot = self.snapshot.clone();
+        user
+    }
+}
+
+/// Session history kept as JSON lines in the data directory.
+///
+/// Lines are only ever appended, a session being written again when it ends
+/// and every few minutes while online. The last line of a session wins and
+/// the file is compacted when opened.
+pub struct SessionStore {
+    file: File,
+    retention: Option<Duration>,
+    records: HashMap<u64, SessionRecord>,
+    // sessions online at the last observe, with when they were last written
+    online: HashMap<u64, DateTime<Local>>,
+}
+
+impl SessionStore {
+    const FILENAME: &'static str = "history.jsonl";
+    const WRITE_ONLINE_EVERY: i64 = 5;
+
+    /// Loads the history in `dir`, forgetting sessions last seen more than
+    /// `retention_days` ago, `0` keeping them forever.
+    pub fn open(dir: &Path, retention_days: u32) -> Result<Self> {
+        Self::open_at(dir, retention_days, Local::now())
+    }
+
+    /// Reads the history in `dir` without touching it, for when another
+    /// gusta may be writing to it.
+    pub fn load(dir: &Path) -> Result<Vec<SessionRecord>> {
+        Ok(Self::read(&dir.join(Self::FILENAME))?
+            .into_values()
+            .collect())
+    }
+
+    fn open_at(dir: &Path, retention_days: u32, now: DateTime<Local>) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(Self::FILENAME);
+        let mut records = Self::read(&path)?;
+
+        let retention = match retention_days {
+            0 => None,
+            days => Some(Duration::days(days.into())),
+        };
+        if let Some(retention) = retention {
+            records.retain(|_, r| now - r.last_seen <= retention);
+        }
+
+        Ok(SessionStore {
+            file: Self::compact(&path, records.values())?,
+            retention,
+            records,
+            online: HashMap::new(),
+        })
+    }
+
+    fn read(path: &Path) -> Result<HashMap<u64, SessionRecord>> {
+        let mut records = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // a line cut short by a crash is lost, not the whole history
+                if let Ok(record) = serde_json::from_str::<SessionRecord>(&line?) {
+                    records.insert(record.device_user().hash_value(), record);
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Whether the session was ever seen.
+    pub fn contains(&self, user: &DeviceUser) -> bool {
+        self.records.contains_key(&user.hash_value())
+    }
+
+    /// Records the sessions online right now.
+    pub fn observe(&mut self, current: &[DeviceUser]) -> Result<()> {
+        self.observe_at(current, Local::now())
+    }
+
+    fn observe_at(&mut self, current: &[DeviceUser], now: DateTime<Local>) -> Result<()> {
+        let mut online = HashMap::new();
+        let mut dirty = HashSet::new();
+        for user in current {
+            let key = user.hash_value();
+            let record = self
+                .records
+                .entry(key)
+                .or_insert_with(|| SessionRecord::new(user, now));
+            record.last_seen = now;
+
+            // new, back online or not written for a while
+            let mut write = match self.online.get(&key) {
+                Some(written) => now - *written >= Duration::minutes(Self::WRITE_ONLINE_EVERY),
+                None => true,
+            };
+            if user.snapshot.is_some() && record.snapshot != user.snapshot {
+                record.snapshot = user.snapshot.clone();
+                write = true;
+            }
+
+            if write {
+                dirty.insert(key);
+                online.insert(key, now);
+            } else {
+                online.insert(key, self.online[&key]);
+            }
+        }
+
+        // ended since the last observe
+        dirty.extend(self.online.keys().filter(|k| !online.contains_key(k)));
+        self.online = online;
+
+        for key in dirty {
+            if let Some(record) = self.records.get(&key) {
+                writeln!(self.file, "{}", serde_json::to_string(record)?)?;
+            }
+        }
+        self.file.flush()?;
+
+        self.forget_expired(now);
+        Ok(())
+    }
+
+    /// Past sessions, the ones in `current` left out.
+    pub fn histories(&self, current: &[DeviceUser]) -> Vec<DeviceUser> {
+        let current = current.iter().map(|c| c.hash_value()).collect::<Vec<u64>>();
+
+        self.records
+            .iter()
+            .filter(|(key, _)| !current.contains(key))
+            .map(|(_, record)| record.device_user())
+            .collect()
+    }
+
+    /// Current and past sessions.
+    pub fn records(&self) -> impl Iterator<Item = &SessionRecord> {
+        self.records.values()
+    }
+
+    fn forget_expired(&mut self, now: DateTime<Local>) {
+        if let Some(retention) = self.retention {
+            let online = &self.online;
+            self.records
+                .retain(|key, r| online.contains_key(key) || now - r.last_seen <= retention);
+        }
+    }
+
+    /// Rewrites the file with one line per session, returning it opened for
+    /// appending.
+    fn compact<'a>(path: &Path, records: impl Iterator<Item = &'a SessionRecord>) -> Result<File> {
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp)?;
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        Ok(OpenOptions::new().append(true).open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::mock_device,
+        testing::{at, temp_dir},
+    };
+
+    fn session(name: &str, ip: &str) -> DeviceUser {
+        DeviceUser::new("nvr", mock_device::user(1, name, ip))
+    }
+
+    #[test]
+    fn history_survives_restart() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let (admin, guest) = (session("admin", "10.0.0.2"), session("guest", "10.0.0.9"));
+
+        let mut store = SessionStore::open_at(dir, 0, at(8, 0)).unwrap();
+        store
+            .observe_at(&[admin.clone(), guest.clone()], at(8, 0))
+            .unwrap();
+        store
+            .observe_at(std::slice::from_ref(&admin), at(8, 10))
+            .unwrap();
+        store
+            .observe_at(std::slice::from_ref(&admin), at(8, 20))
+            .unwrap();
+        drop(store);
+
+        let store = SessionStore::open_at(dir, 0, at(9, 0)).unwrap();
+        let mut records = store.records.values().cloned().collect::<Vec<_>>();
+        records.sort_by(|a, b| a.user.cmp(&b.user));
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].user, "admin");
+        assert_eq!(records[0].first_seen, at(8, 0));
+        // written every few minutes while online
+        assert_eq!(records[0].last_seen, at(8, 20));
+        assert_eq!(records[1].ip, "10.0.0.9");
+        assert_eq!(records[1].last_seen, at(8, 0));
+        assert!(store.contains(&guest));
+        assert_eq!(store.histories(&[admin]), vec![guest]);
+
+        let lines = fs::read_to_string(dir.join(SessionStore::FILENAME)).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert_eq!(SessionStore::load(dir).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn old_sessions_are_forgotten() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let mut store = SessionStore::open_at(dir, 0, at(8, 0)).unwrap();
+        store
+            .observe_at(&[session("admin", "10.0.0.2")], at(8, 0))
+            .unwrap();
+        store.observe_at(&[], at(8, 1)).unwrap();
+        drop(store);
+
+        let later = at(8, 0) + Duration::days(31);
+        let store = SessionStore::open_at(dir, 30, later).unwrap();
+        assert_eq!(store.records.len(), 0);
+
+        let store = SessionStore::open_at(dir, 30, later).unwrap();
+        assert_eq!(store.records.len(), 0);
+    }
+
+    #[test]
+    fn torn_line_is_skipped() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let mut store = SessionStore::open_at(dir, 0, at(8, 0)).unwrap();
+        store
+            .observe_at(&[session("admin", "10.0.0.2")], at(8, 0))
+            .unwrap();
+        drop(store);
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(SessionStore::FILENAME))
+            .unwrap();
+        write!(file, "{{\"first_seen\":\"2023-").unwrap();
+
+        let store = SessionStore::open_at(dir, 0, at(9, 0)).unwrap();
+        assert_eq!(store.records.len(), 1);
+    }
+}
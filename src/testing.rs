@@ -0,0 +1,24 @@
+//! Fixtures shared by the tests of every module.
+
+use chrono::{DateTime, Local, NaiveDate};
+use tempfile::TempDir;
+
+/// The day the tests take place on, a thursday.
+pub fn day() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2023, 11, 2).unwrap()
+}
+
+/// `hour:min` local time on [`day`].
+pub fn at(hour: u32, min: u32) -> DateTime<Local> {
+    day()
+        .and_hms_opt(hour, min, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .unwrap()
+}
+
+/// A directory of its own, removed with what is in it once dropped, failed
+/// assertion or not.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new().prefix("gusta-").tempdir().unwrap()
+}
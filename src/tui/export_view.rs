@@ -0,0 +1,143 @@
+use chrono::{Datelike, Local, NaiveDate};
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use std::{
+    fs::File,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    export::{self, ExportFilter, ExportFormat},
+    store::SessionStore,
+};
+
+mod view_names {
+    pub const FORMAT: &str = "export_format_sel";
+    pub const FROM: &str = "export_from_edit";
+    pub const TO: &str = "export_to_edit";
+    pub const USER: &str = "export_user_edit";
+    pub const IP: &str = "export_ip_edit";
+    pub const FILE: &str = "export_file_edit";
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Asks what to export, then writes the session history to a file.
+pub fn open(s: &mut Cursive, store: Arc<Mutex<SessionStore>>) {
+    let today = Local::now().date_naive();
+    let month_start = today.with_day0(0).unwrap_or(today);
+
+    let edit = |name: &str, content: String| EditView::new().content(content).with_name(name);
+    let form = LinearLayout::vertical()
+        .child(TextView::new("Format"))
+        .child(
+            SelectView::new()
+                .popup()
+                .with_all(ExportFormat::ALL.map(|f| (f.to_string(), f)))
+                .with_name(view_names::FORMAT),
+        )
+        .child(TextView::new(format!(
+            "From ({}, empty for any)",
+            DATE_FORMAT
+        )))
+        .child(edit(
+            view_names::FROM,
+            month_start.format(DATE_FORMAT).to_string(),
+        ))
+        .child(TextView::new(format!(
+            "To ({}, empty for any)",
+            DATE_FORMAT
+        )))
+        .child(edit(view_names::TO, today.format(DATE_FORMAT).to_string()))
+        .child(TextView::new("User (empty for any)"))
+        .child(edit(view_names::USER, String::new()))
+        .child(TextView::new("IP (empty for any)"))
+        .child(edit(view_names::IP, String::new()))
+        .child(TextView::new("File, the extension is added when missing"))
+        .child(edit(
+            view_names::FILE,
+            format!("history-{}", today.format("%Y%m%d")),
+        ));
+
+    s.add_layer(
+        Dialog::around(form)
+            .title("Export session history")
+            .button("Export", move |s| write(s, &store))
+            .dismiss_button("Cancel")
+            .min_width(60),
+    );
+}
+
+fn write(s: &mut Cursive, store: &Mutex<SessionStore>) {
+    let format = s
+        .call_on_name(view_names::FORMAT, |v: &mut SelectView<ExportFormat>| {
+            v.selection()
+        })
+        .flatten()
+        .map(|f| *f)
+        .unwrap_or(ExportFormat::Csv);
+    let filter = match read_filter(s) {
+        Ok(filter) => filter,
+        Err(e) => return s.add_layer(Dialog::info(e)),
+    };
+    let mut path = PathBuf::from(read_text(s, view_names::FILE));
+    if path.extension().is_none() {
+        path.set_extension(format.extension());
+    }
+
+    let res = File::create(&path)
+        .map_err(Into::into)
+        .and_then(|mut file| {
+            let store = store.lock().unwrap();
+            export::export(store.records(), &filter, format, &mut file)
+        });
+
+    match res {
+        Ok(count) => {
+            s.pop_layer();
+            s.add_layer(Dialog::info(format!(
+                "Exported {} sessions to {}",
+                count,
+                path.display()
+            )));
+        }
+        Err(e) => s.add_layer(Dialog::info(format!("{}: {}", path.display(), e))),
+    }
+}
+
+fn read_filter(s: &mut Cursive) -> Result<ExportFilter, String> {
+    let mut read_date = |name: &str| {
+        let text = read_text(s, name);
+        if text.is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(&text, DATE_FORMAT)
+            .map(Some)
+            .map_err(|_| format!("{} is not a {} date", text, DATE_FORMAT))
+    };
+    let from = read_date(view_names::FROM)?;
+    let to = read_date(view_names::TO)?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err("From must not be after To".into());
+        }
+    }
+
+    let mut read_opt = |name: &str| Some(read_text(s, name)).filter(|t| !t.is_empty());
+    Ok(ExportFilter {
+        from,
+        to,
+        user: read_opt(view_names::USER),
+        ip: read_opt(view_names::IP),
+    })
+}
+
+fn read_text(s: &mut Cursive, name: &str) -> String {
+    s.call_on_name(name, |v: &mut EditView| v.get_content())
+        .map(|t| t.trim().to_string())
+        .unwrap_or_default()
+}
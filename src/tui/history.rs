@@ -24,10 +24,15 @@ impl<T: Hashable + Clone> HistManager<T> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.hist.clear()
     }
 
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.hist.retain(|_, value| keep(value))
+    }
+
     pub fn histories(&self, current: &[T]) -> Vec<T> {
         let current_h = current.iter().map(|c| c.hash_value()).collect::<Vec<u64>>();
 
@@ -0,0 +1,154 @@
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use cursive_table_view::TableView;
+
+use super::{pick_device, table::UserColumn, view_names as main_views};
+use crate::{client::IPFilter, monitor::Control, monitor::DeviceUser};
+
+mod view_names {
+    pub const ENTRIES: &str = "ip_filter_sel";
+    pub const MODE: &str = "ip_filter_mode_txt";
+}
+
+/// Asks before adding the IP of the selected online user to its device's
+/// deny list.
+pub fn confirm_block(s: &mut Cursive, control: Control) {
+    let selected = s
+        .call_on_name(
+            main_views::ONLINE_USER,
+            |t: &mut TableView<DeviceUser, UserColumn>| {
+                t.item().and_then(|i| t.borrow_item(i).cloned())
+            },
+        )
+        .flatten();
+    let selected = match selected {
+        Some(u) => u,
+        None => return s.add_layer(Dialog::info("Select an online user first")),
+    };
+
+    let device = selected.device;
+    let ip = selected.user.client_address.ip_address;
+    s.add_layer(
+        Dialog::text(format!(
+            "Block {} ({}) on {}?\nThe device will refuse every connection from it.\n\
+            A disabled IP filter gets enabled, unless it still holds old \
+            entries, as those would be enforced again too.",
+            ip, selected.user.name, device
+        ))
+        .title("Block IP")
+        .button("Block", move |s| {
+            s.pop_layer();
+            let (control, device, ip) = (control.clone(), device.clone(), ip.clone());
+            let sink = s.cb_sink().clone();
+            tokio::spawn(async move {
+                let res = control.deny_ip(&device, &ip).await;
+                let _res = sink.send(Box::new(move |s| match res {
+                    Ok(_) => s.add_layer(Dialog::info(format!("{} blocked on {}", ip, device))),
+                    Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+                }));
+            });
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+/// Lists the IP filter of a device and lets entries be removed.
+pub fn open_list(s: &mut Cursive, control: Control, devices: Vec<String>) {
+    pick_device(s, "IP filter of", devices, move |s, device| {
+        load(s, control.clone(), device)
+    });
+}
+
+fn load(s: &mut Cursive, control: Control, device: String) {
+    let sink = s.cb_sink().clone();
+    tokio::spawn(async move {
+        let res = control.ip_filter(&device).await;
+        let _res = sink.send(Box::new(move |s| match res {
+            Ok(filter) => show(s, control, device, filter),
+            Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+        }));
+    });
+}
+
+fn show(s: &mut Cursive, control: Control, device: String, filter: IPFilter) {
+    let mut entries = SelectView::<u32>::new();
+    fill(&mut entries, &filter);
+
+    let title = format!("IP filter: {}", device);
+    s.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(TextView::new(mode(&filter)).with_name(view_names::MODE))
+                .child(entries.with_name(view_names::ENTRIES).scrollable()),
+        )
+        .title(title)
+        .button("Remove", move |s| {
+            confirm_remove(s, control.clone(), device.clone())
+        })
+        .dismiss_button("Close")
+        .min_width(50),
+    );
+}
+
+fn confirm_remove(s: &mut Cursive, control: Control, device: String) {
+    let selected = s
+        .call_on_name(view_names::ENTRIES, |v: &mut SelectView<u32>| {
+            v.selection().zip(
+                v.selected_id()
+                    .and_then(|i| v.get_item(i))
+                    .map(|(l, _)| l.to_string()),
+            )
+        })
+        .flatten();
+    let (id, label) = match selected {
+        Some((id, label)) => (*id, label),
+        None => return,
+    };
+
+    s.add_layer(
+        Dialog::text(format!(
+            "Remove {} from the IP filter of {}?",
+            label, device
+        ))
+        .button("Remove", move |s| {
+            s.pop_layer();
+            let (control, device) = (control.clone(), device.clone());
+            let sink = s.cb_sink().clone();
+            tokio::spawn(async move {
+                let res = control.remove_ip_filter(&device, id).await;
+                let _res = sink.send(Box::new(move |s| match res {
+                    Ok(filter) => {
+                        s.call_on_name(view_names::ENTRIES, |v: &mut SelectView<u32>| {
+                            fill(v, &filter)
+                        });
+                        s.call_on_name(view_names::MODE, |t: &mut TextView| {
+                            t.set_content(mode(&filter))
+                        });
+                    }
+                    Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+                }));
+            });
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+fn fill(entries: &mut SelectView<u32>, filter: &IPFilter) {
+    entries.clear();
+    for address in &filter.list.addresses {
+        entries.add_item(
+            format!("{:>4}  {}", address.id, address.address_mask.ip_address),
+            address.id,
+        );
+    }
+}
+
+fn mode(filter: &IPFilter) -> String {
+    match (filter.enabled, filter.list.addresses.len()) {
+        (false, _) => "Disabled".into(),
+        (true, n) => format!("{} list, {} entries", filter.permission_type, n),
+    }
+}
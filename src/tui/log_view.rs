@@ -0,0 +1,133 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Checkbox, Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+
+use super::table::build_log_table;
+use crate::{
+    client::{LogMinorType, LogQuery},
+    monitor::Control,
+};
+
+mod view_names {
+    pub const DEVICE: &str = "log_device_sel";
+    pub const FROM: &str = "log_from_edit";
+    pub const TO: &str = "log_to_edit";
+    pub const SEARCHING: &str = "log_searching_txt";
+}
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Asks for a device and time range, then shows what the device logged.
+pub fn open_search(s: &mut Cursive, control: Control, devices: Vec<String>) {
+    let now = Local::now().naive_local();
+    let since = now - Duration::hours(12);
+
+    let mut types = LinearLayout::horizontal();
+    for minor_type in LogMinorType::ALL {
+        types.add_child(Checkbox::new().checked().with_name(minor_type.as_str()));
+        types.add_child(TextView::new(format!(" {}  ", minor_type.label())));
+    }
+
+    let form = LinearLayout::vertical()
+        .child(TextView::new("Device"))
+        .child(
+            SelectView::new()
+                .popup()
+                .with_all_str(devices)
+                .with_name(view_names::DEVICE),
+        )
+        .child(TextView::new(format!("From ({})", TIME_FORMAT)))
+        .child(
+            EditView::new()
+                .content(since.format(TIME_FORMAT).to_string())
+                .with_name(view_names::FROM),
+        )
+        .child(TextView::new(format!("To ({})", TIME_FORMAT)))
+        .child(
+            EditView::new()
+                .content(now.format(TIME_FORMAT).to_string())
+                .with_name(view_names::TO),
+        )
+        .child(types);
+
+    s.add_layer(
+        Dialog::around(form)
+            .title("Search device log")
+            .button("Search", move |s| search(s, control.clone()))
+            .dismiss_button("Cancel")
+            .min_width(60),
+    );
+}
+
+fn search(s: &mut Cursive, control: Control) {
+    let device = s
+        .call_on_name(view_names::DEVICE, |v: &mut SelectView| v.selection())
+        .flatten();
+    let query = read_query(s);
+
+    let (device, query) = match (device, query) {
+        (Some(device), Ok(query)) => ((*device).clone(), query),
+        (None, _) => return s.add_layer(Dialog::info("No device to search")),
+        (_, Err(e)) => return s.add_layer(Dialog::info(e)),
+    };
+
+    s.pop_layer();
+    s.add_layer(
+        Dialog::around(TextView::new(format!("Searching {}...", device)))
+            .with_name(view_names::SEARCHING),
+    );
+
+    let sink = s.cb_sink().clone();
+    tokio::spawn(async move {
+        let res = control.search_log(&device, query).await;
+        let _res = sink.send(Box::new(move |s| {
+            if let Some(pos) = s.screen_mut().find_layer_from_name(view_names::SEARCHING) {
+                s.screen_mut().remove_layer(pos);
+            }
+
+            match res {
+                Ok(entries) => s.add_layer(
+                    Dialog::around(build_log_table(entries).full_screen())
+                        .title(format!("Device log: {}", device))
+                        .dismiss_button("Close"),
+                ),
+                Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+            }
+        }));
+    });
+}
+
+fn read_query(s: &mut Cursive) -> Result<LogQuery, String> {
+    let mut read_time = |name: &str| {
+        let text = s
+            .call_on_name(name, |v: &mut EditView| v.get_content())
+            .unwrap_or_default();
+        NaiveDateTime::parse_from_str(text.trim(), TIME_FORMAT)
+            .map_err(|_| format!("{} is not a {} time", text, TIME_FORMAT))
+    };
+    let start = read_time(view_names::FROM)?;
+    let end = read_time(view_names::TO)?;
+    if start >= end {
+        return Err("From must be before To".into());
+    }
+
+    let minor_types = LogMinorType::ALL
+        .into_iter()
+        .filter(|t| {
+            s.call_on_name(t.as_str(), |c: &mut Checkbox| c.is_checked())
+                .unwrap_or(false)
+        })
+        .collect::<Vec<LogMinorType>>();
+    if minor_types.is_empty() {
+        return Err("Pick at least one event type".into());
+    }
+
+    Ok(LogQuery {
+        start,
+        end,
+        minor_types,
+    })
+}
@@ -0,0 +1,106 @@
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Checkbox, Dialog, EditView, LinearLayout, TextView},
+    Cursive,
+};
+
+use super::pick_device;
+use crate::{client::IllegalLoginLock, monitor::Control};
+
+mod view_names {
+    pub const ENABLED: &str = "lock_enabled_chk";
+    pub const MAX_ATTEMPTS: &str = "lock_attempts_edit";
+    pub const DURATION: &str = "lock_duration_edit";
+}
+
+/// Shows the illegal login lock policy of a device for editing. Which
+/// accounts it has locked is not part of the policy, the banner only knows
+/// about the account gusta logs in with.
+pub fn open(s: &mut Cursive, control: Control, devices: Vec<String>) {
+    pick_device(s, "Lock policy of", devices, move |s, device| {
+        let control = control.clone();
+        let sink = s.cb_sink().clone();
+        tokio::spawn(async move {
+            let res = control.login_lock(&device).await;
+            let _res = sink.send(Box::new(move |s| match res {
+                Ok(lock) => show(s, control, device, lock),
+                Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+            }));
+        });
+    });
+}
+
+fn show(s: &mut Cursive, control: Control, device: String, lock: IllegalLoginLock) {
+    let form = LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(lock.enabled)
+                        .with_name(view_names::ENABLED),
+                )
+                .child(TextView::new(" Lock accounts after failed logins")),
+        )
+        .child(TextView::new(
+            "A lock of gusta's own account shows in the banner, others can't be seen.",
+        ))
+        .child(TextView::new("Failed logins before locking"))
+        .child(
+            EditView::new()
+                .content(lock.max_attempts.to_string())
+                .with_name(view_names::MAX_ATTEMPTS),
+        )
+        .child(TextView::new("Lock duration (minutes)"))
+        .child(
+            EditView::new()
+                .content(lock.lock_duration.to_string())
+                .with_name(view_names::DURATION),
+        );
+
+    let title = format!("Lock policy: {}", device);
+    s.add_layer(
+        Dialog::around(form)
+            .title(title)
+            .button("Save", move |s| save(s, control.clone(), device.clone()))
+            .dismiss_button("Cancel")
+            .min_width(40),
+    );
+}
+
+fn save(s: &mut Cursive, control: Control, device: String) {
+    let lock = match read_lock(s) {
+        Ok(lock) => lock,
+        Err(e) => return s.add_layer(Dialog::info(e)),
+    };
+
+    s.pop_layer();
+    let sink = s.cb_sink().clone();
+    tokio::spawn(async move {
+        let res = control.update_login_lock(&device, lock).await;
+        let _res = sink.send(Box::new(move |s| match res {
+            Ok(_) => s.add_layer(Dialog::info(format!("Lock policy of {} saved", device))),
+            Err(e) => s.add_layer(Dialog::info(format!("{}: {}", device, e))),
+        }));
+    });
+}
+
+fn read_lock(s: &mut Cursive) -> Result<IllegalLoginLock, String> {
+    let enabled = s
+        .call_on_name(view_names::ENABLED, |c: &mut Checkbox| c.is_checked())
+        .unwrap_or(false);
+    let mut read_number = |name: &str, label: &str| {
+        let text = s
+            .call_on_name(name, |v: &mut EditView| v.get_content())
+            .unwrap_or_default();
+        match text.trim().parse::<u32>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("{} must be a positive number", label)),
+        }
+    };
+
+    Ok(IllegalLoginLock {
+        enabled,
+        max_attempts: read_number(view_names::MAX_ATTEMPTS, "Failed logins")?,
+        lock_duration: read_number(view_names::DURATION, "Lock duration")?,
+    })
+}
@@ -1,54 +1,93 @@
 use self::{
     audio::SoundBank,
-    table::{build_table, UserColumn},
-    theme::dark, history::HistManager,
+    history::HistManager,
+    table::{build_alert_table, build_channel_table, build_disk_table, build_table, AlertColumn},
 };
 use crate::{
-    api_provider::WebEndpoint,
     assets,
-    client::{HikClient, OnlineUser},
+    client::Hashable,
     config::Config,
+    monitor::{
+        Aggregator, DeviceAlert, DeviceChange, DeviceChannel, DeviceEvent, DeviceUser, Monitor,
+        Snapshots,
+    },
+    notify::{ErrorHandler, Notifier},
+    rules::{RuleAction, SessionChange},
+    store::SessionStore,
 };
 use anyhow::{Error, Result};
+use chrono::Local;
 use cursive::{
     align::HAlign,
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::Nameable,
     view::Resizable,
-    views::{Dialog, LinearLayout, TextView},
-    CbSink, CursiveRunnable,
+    view::Scrollable,
+    views::{Dialog, LinearLayout, SelectView, TextView},
+    CbSink, Cursive, CursiveRunnable,
+};
+use cursive_table_view::{TableView, TableViewItem};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    mem,
+    path::PathBuf,
+    sync::{Arc, Mutex as StdMutex},
+};
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, Mutex},
+    task::JoinHandle,
 };
-use cursive_table_view::TableView;
-use std::{sync::Arc, time::Duration};
-use tokio::{sync::Mutex, task::JoinHandle, time};
+
+pub use theme::ThemeName;
 
 mod audio;
+mod export_view;
+mod history;
+mod ip_filter_view;
+mod log_view;
+mod login_lock_view;
 mod table;
 mod theme;
-mod history;
 
 enum Status {
     Idle,
     Running {
-        cursive: CursiveRunnable,
+        cursive: Box<CursiveRunnable>,
         fetch_jh: JoinHandle<()>,
     },
 }
 pub struct AppTui {
     status: Status,
     config: Arc<Config>,
+    // taken once started
+    reloads: Option<UnboundedReceiver<Result<Config>>>,
     audio_man: Arc<Mutex<SoundBank>>,
 }
 
 mod view_names {
     pub const ONLINE_USER: &str = "online_tbl";
     pub const HISTORY: &str = "history_tbl";
+    pub const ALERTS: &str = "alerts_tbl";
+    pub const CHANNELS: &str = "channels_tbl";
+    pub const STORAGE: &str = "storage_tbl";
+    pub const STATUS: &str = "status_txt";
+    pub const DEVICE_INFO: &str = "device_info_txt";
+    pub const BANNER: &str = "banner_txt";
+    pub const NOTICES: &str = "notices_txt";
+    pub const CONFIG_ERROR: &str = "config_error_txt";
 }
 
 impl AppTui {
-    const FETCH_USER_DEPLAY: u64 = 1111;
+    const MAX_ALERTS: usize = 200;
 
-    pub fn new(conf: Config) -> Result<Self> {
+    /// `reloads` being the config every time it changes, see
+    /// [`ConfigSource::watch`](crate::config::ConfigSource::watch).
+    pub fn new(conf: Config, reloads: UnboundedReceiver<Result<Config>>) -> Result<Self> {
         Ok(Self {
             config: Arc::new(conf),
+            reloads: Some(reloads),
             status: Status::Idle,
             audio_man: Arc::new(Mutex::new(SoundBank::from_array(assets::alert_sound())?)),
         })
@@ -62,6 +101,13 @@ impl AppTui {
 
         siv.add_fullscreen_layer(
             LinearLayout::vertical()
+                .child(TextView::empty().with_name(view_names::BANNER))
+                .child(
+                    Dialog::new()
+                        .title("Devices")
+                        .content(TextView::empty().with_name(view_names::DEVICE_INFO))
+                        .full_width(),
+                )
                 .child(
                     Dialog::new()
                         .title("Online")
@@ -74,7 +120,35 @@ impl AppTui {
                         .content(build_table(view_names::HISTORY))
                         .full_screen(),
                 )
-                .child(TextView::new("Press q to quit").h_align(HAlign::Right)),
+                .child(
+                    LinearLayout::horizontal()
+                        .child(
+                            Dialog::new()
+                                .title("Events")
+                                .content(build_alert_table(view_names::ALERTS))
+                                .full_screen(),
+                        )
+                        .child(
+                            Dialog::new()
+                                .title("Channels")
+                                .content(build_channel_table(view_names::CHANNELS))
+                                .full_screen(),
+                        )
+                        .child(
+                            Dialog::new()
+                                .title("Storage")
+                                .content(build_disk_table(view_names::STORAGE))
+                                .full_screen(),
+                        ),
+                )
+                .child(
+                    LinearLayout::horizontal()
+                        .child(TextView::empty().with_name(view_names::STATUS).full_width())
+                        .child(TextView::new(
+                            "Press b to block IP, f for IP filter, p for lock policy, l for device log, e to export  ",
+                        ))
+                        .child(TextView::new("Press q to quit").h_align(HAlign::Right)),
+                ),
             // .child(TextView::new("Press c to clear").h_align(HAlign::Right)),
         );
         let sink = siv.cb_sink().clone();
@@ -84,90 +158,452 @@ impl AppTui {
 
     pub async fn start(&mut self) -> Result<()> {
         // captures
-        let mut client = HikClient::new(
-            &self.config.username,
-            &self.config.password,
-            WebEndpoint::new(&self.config.endpoint),
-        );
-        client.login().await?;
+        let mut reloads = self
+            .reloads
+            .take()
+            .ok_or(Error::msg("app already started"))?;
+        let (monitor, mut updates) = Monitor::start(&self.config.devices);
+        monitor.set_poll_interval(self.config.poll_interval());
 
         let (mut siv, sink) = Self::build_tui();
         let conf = self.config.clone();
+
+        let control = monitor.control();
+        {
+            let control = control.clone();
+            siv.add_global_callback('l', move |s| {
+                log_view::open_search(s, control.clone(), control.devices())
+            });
+        }
+        {
+            let control = control.clone();
+            siv.add_global_callback('b', move |s| {
+                ip_filter_view::confirm_block(s, control.clone())
+            });
+        }
+        {
+            let control = control.clone();
+            siv.add_global_callback('p', move |s| {
+                login_lock_view::open(s, control.clone(), control.devices())
+            });
+        }
+        {
+            let control = control.clone();
+            siv.add_global_callback('f', move |s| {
+                ip_filter_view::open_list(s, control.clone(), control.devices())
+            });
+        }
+        let snapshots = Snapshots::new(&conf.snapshot_dir);
+        let mut snapshot_channels = Self::snapshot_channels(&conf);
+        let store = Arc::new(StdMutex::new(SessionStore::open(
+            &conf.data_dir,
+            conf.history_retention_days,
+        )?));
+        {
+            let store = store.clone();
+            siv.add_global_callback('e', move |s| export_view::open(s, store.clone()));
+        }
+        let on_error: ErrorHandler = {
+            let sink = sink.clone();
+            Arc::new(move |e| Self::set_status(&sink, format!("Notification lost: {}", e)))
+        };
+        let mut notifier = Notifier::start(&conf, on_error.clone())?;
         let sb = self.audio_man.clone();
+        siv.set_theme(conf.theme.theme());
 
         let fetch_jh = tokio::spawn(async move {
-            let mut hist_mngr = HistManager::new();
-            let mut interval = time::interval(Duration::from_millis(Self::FETCH_USER_DEPLAY));
-            let mut last_cur_count: usize = 0;
+            // pollers stop once this task is aborted
+            let mut monitor = monitor;
+            let mut aggregator = Aggregator::new(conf.devices.iter().map(|d| d.name.as_str()));
+            let mut rules = conf.rules();
+            // channels seen online at least once, so cameras already down at
+            // startup stay quiet
+            let mut seen_online = HistManager::<DeviceChannel>::new();
+            let mut last_offline: Vec<DeviceChannel> = vec![];
+            let mut last_problems = vec![];
+            // told once until the store works again, not on every poll
+            let mut history_failing = false;
+            // filled in by capture tasks, keyed by session
+            let snapshot_links = Arc::new(StdMutex::new(HashMap::<u64, PathBuf>::new()));
             loop {
-                interval.tick().await;
+                let update = tokio::select! {
+                    update = updates.recv() => match update {
+                        Some(update) => update,
+                        None => break,
+                    },
+                    Some(reload) = reloads.recv() => {
+                        // targets that fail to start fail the whole reload
+                        let reload = reload.and_then(|conf| {
+                            let notifier = Notifier::start(&conf, on_error.clone())?;
+                            Ok((conf, notifier))
+                        });
+                        let conf = match reload {
+                            Ok((conf, new_notifier)) => {
+                                notifier = new_notifier;
+                                conf
+                            }
+                            Err(e) => {
+                                Self::show_config_error(&sink, format!("{:#}", e));
+                                continue;
+                            }
+                        };
 
-                let online = match client.fetch_online_users().await {
-                    Ok(o) => o,
-                    Err(_e) => {
-                        // TODO show error
+                        monitor.set_devices(&conf.devices);
+                        monitor.set_poll_interval(conf.poll_interval());
+                        aggregator.set_devices(conf.devices.iter().map(|d| d.name.as_str()));
+                        // a device added back later starts afresh
+                        let names = conf
+                            .devices
+                            .iter()
+                            .map(|d| d.name.as_str())
+                            .collect::<HashSet<&str>>();
+                        seen_online.retain(|c| names.contains(c.device.as_str()));
+                        last_offline.retain(|c| names.contains(c.device.as_str()));
+                        last_problems.retain(|(device, ..): &(String, _, _)| names.contains(device.as_str()));
+                        rules = conf.rules();
+                        snapshot_channels = Self::snapshot_channels(&conf);
+                        let theme = conf.theme.theme();
+                        let _res = sink.send(Box::new(move |s| s.set_theme(theme)));
+                        Self::set_status(&sink, aggregator.status());
+                        Self::set_device_info(&sink, &aggregator);
+                        Self::set_banner(&sink, &aggregator);
+                        let current = Self::online(&aggregator, &snapshot_links);
+                        let hist = store.lock().unwrap().histories(&current);
+                        Self::set_table(&sink, view_names::ONLINE_USER, current);
+                        Self::set_table(&sink, view_names::HISTORY, hist);
+                        Self::set_table(&sink, view_names::CHANNELS, aggregator.channels());
+                        Self::set_table(&sink, view_names::STORAGE, aggregator.disks());
                         continue;
                     }
                 };
 
-                hist_mngr.add_vec(&online.users);
-                let hist = hist_mngr
-                    .histories(&online.users)
-                    .into_iter()
-                    .filter(|o| o.name != conf.username)
-                    .collect::<Vec<OnlineUser>>();
+                if let DeviceEvent::Alert(alert) = update.event {
+                    Self::ring(&sb, &sink).await;
+
+                    let alert = DeviceAlert::new(&update.device, alert);
+                    let _res = sink.send(Box::new(|s| {
+                        s.call_on_name(
+                            view_names::ALERTS,
+                            |t: &mut TableView<DeviceAlert, AlertColumn>| {
+                                t.insert_item(alert);
+                                // items are kept in arrival order whatever the sort
+                                while t.len() > Self::MAX_ALERTS {
+                                    t.remove_item(0);
+                                }
+                            },
+                        );
+                    }));
+                    continue;
+                }
+
+                let info_changed = matches!(update.event, DeviceEvent::Info(_));
+                let state_changed = matches!(update.event, DeviceEvent::State(_));
+                let channels_changed = matches!(update.event, DeviceEvent::Channels(_));
+                let storage_changed = matches!(update.event, DeviceEvent::Storage(_));
+                let changes = aggregator.apply(update);
+                Self::set_status(&sink, aggregator.status());
+
+                let now = Local::now();
+                let mut play = false;
+                let mut notices = vec![];
+                let mut started = vec![];
+                for change in &changes {
+                    let action = notifier.change(change, &rules, now);
+                    let DeviceChange::Session {
+                        change,
+                        user,
+                        initial,
+                    } = change
+                    else {
+                        continue;
+                    };
+                    // the sessions found on the first listing are not new
+                    if *change == SessionChange::Started && !initial {
+                        started.push(user.clone());
+                    }
+                    match action {
+                        RuleAction::Ignore => {}
+                        RuleAction::Sound => play = true,
+                        RuleAction::Notify => {
+                            play = true;
+                            notices.push(format!(
+                                "{} {}: {} {} from {}",
+                                now.format("%H:%M:%S"),
+                                user.device,
+                                user.user.name,
+                                change,
+                                user.user.client_address.ip_address
+                            ));
+                        }
+                    }
+                }
+                if play {
+                    Self::ring(&sb, &sink).await;
+                }
+                if !notices.is_empty() {
+                    Self::notify(&sink, notices);
+                }
+
+                if info_changed {
+                    Self::set_device_info(&sink, &aggregator);
+                }
+                if state_changed || storage_changed {
+                    Self::set_banner(&sink, &aggregator);
+                }
+                if storage_changed {
+                    let disks = aggregator.disks();
+                    let problems = disks
+                        .iter()
+                        .filter_map(|d| {
+                            let problem = d.disk.problem.as_ref()?;
+                            Some((d.device.clone(), d.disk.hdd.id, mem::discriminant(problem)))
+                        })
+                        .collect::<Vec<_>>();
+
+                    // play alert if a disk got a new problem since last poll
+                    if problems.iter().any(|p| !last_problems.contains(p)) {
+                        Self::ring(&sb, &sink).await;
+                    }
+                    last_problems = problems;
+
+                    Self::set_table(&sink, view_names::STORAGE, disks);
+                    continue;
+                }
+                if channels_changed {
+                    let channels = aggregator.channels();
+                    let online = channels
+                        .iter()
+                        .filter(|c| c.channel.online)
+                        .cloned()
+                        .collect::<Vec<DeviceChannel>>();
+                    seen_online.add_vec(&online);
+                    let offline = seen_online.histories(&online);
+
+                    // play alert if a channel went offline since last poll
+                    if offline.iter().any(|c| !last_offline.contains(c)) {
+                        Self::ring(&sb, &sink).await;
+                    }
+                    last_offline = offline;
+
+                    Self::set_table(&sink, view_names::CHANNELS, channels);
+                    continue;
+                }
 
-                let current = online
-                    .users
+                let new = started
                     .into_iter()
-                    .filter(|o| o.name != conf.username)
-                    .collect::<Vec<OnlineUser>>();
-
-                // play alert if cur online count changed
-                if current.len() != last_cur_count {
-                    // TODO handle result err
-                    let mut sb_lock = sb.lock().await;
-                    sb_lock.play().unwrap();
+                    .filter(|u| !store.lock().unwrap().contains(u));
+                for user in new {
+                    let Some(channels) = snapshot_channels.get(&user.device) else {
+                        continue;
+                    };
+                    if channels.is_empty() {
+                        continue;
+                    }
+                    let (snapshots, control) = (snapshots.clone(), control.clone());
+                    let channels = channels.clone();
+                    let links = snapshot_links.clone();
+                    let sink = sink.clone();
+                    tokio::spawn(async move {
+                        match snapshots.capture(&control, &user, &channels, now).await {
+                            Ok(dir) => {
+                                links.lock().unwrap().insert(user.hash_value(), dir);
+                            }
+                            // the status line would be overwritten by the next poll
+                            Err(e) => Self::notify(
+                                &sink,
+                                vec![format!(
+                                    "{} {}: no snapshot of {}: {:#}",
+                                    now.format("%H:%M:%S"),
+                                    user.device,
+                                    user.user.name,
+                                    e
+                                )],
+                            ),
+                        }
+                    });
                 }
-                last_cur_count = current.len();
+                let current = Self::online(&aggregator, &snapshot_links);
+                let hist = {
+                    let mut store = store.lock().unwrap();
+                    match store.observe(&current) {
+                        Ok(()) => history_failing = false,
+                        // the status line would be overwritten by the next poll
+                        Err(e) if !history_failing => {
+                            history_failing = true;
+                            Self::notify(
+                                &sink,
+                                vec![format!(
+                                    "{} History not saved: {:#}",
+                                    now.format("%H:%M:%S"),
+                                    e
+                                )],
+                            );
+                        }
+                        Err(_) => {}
+                    }
+                    store.histories(&current)
+                };
 
                 // updates TUI
-                let _res = sink.clone().send(Box::new(|s| {
-                    s.call_on_name(
-                        view_names::ONLINE_USER,
-                        |t: &mut TableView<OnlineUser, UserColumn>| {
-                            t.set_items_stable(current);
-                        },
-                    );
-                }));
-                // if res.is_err() {}
-
-                let _res = sink.clone().send(Box::new(|s| {
-                    s.call_on_name(
-                        view_names::HISTORY,
-                        |t: &mut TableView<OnlineUser, UserColumn>| {
-                            t.set_items_stable(hist);
-                        },
-                    );
-                }));
-                // TODO show error
-                // if res.is_err() {}
+                Self::set_table(&sink, view_names::ONLINE_USER, current);
+                Self::set_table(&sink, view_names::HISTORY, hist);
             }
         });
-        siv.set_theme(dark());
         siv.run();
 
         self.status = Status::Running {
-            cursive: siv,
+            cursive: Box::new(siv),
             fetch_jh,
         };
 
         Ok(())
     }
 
+    /// Pops up sessions rules asked to be told about, adding to the popup
+    /// when it is still open.
+    fn notify(sink: &CbSink, lines: Vec<String>) {
+        let _res = sink.send(Box::new(move |s| {
+            let text = lines.join("\n");
+            let added = s
+                .call_on_name(view_names::NOTICES, |t: &mut TextView| {
+                    t.append(format!("\n{}", text))
+                })
+                .is_some();
+            if !added {
+                s.add_layer(
+                    Dialog::around(
+                        TextView::new(text)
+                            .with_name(view_names::NOTICES)
+                            .scrollable(),
+                    )
+                    .title("Sessions")
+                    .dismiss_button("OK"),
+                );
+            }
+        }));
+    }
+
+    /// Pops up why the config was not reloaded, replacing the previous
+    /// reason when it is still open.
+    fn show_config_error(sink: &CbSink, text: String) {
+        let _res = sink.send(Box::new(move |s| {
+            let replaced = s
+                .call_on_name(view_names::CONFIG_ERROR, |t: &mut TextView| {
+                    t.set_content(text.clone())
+                })
+                .is_some();
+            if !replaced {
+                s.add_layer(
+                    Dialog::around(TextView::new(text).with_name(view_names::CONFIG_ERROR))
+                        .title("Config not reloaded, keeping the previous one")
+                        .dismiss_button("OK"),
+                );
+            }
+        }));
+    }
+
+    fn snapshot_channels(conf: &Config) -> HashMap<String, Vec<u32>> {
+        conf.devices
+            .iter()
+            .map(|d| (d.name.clone(), d.snapshot_channels.clone()))
+            .collect()
+    }
+
+    /// Red lines for failing disks and for the account gusta logs in with
+    /// being locked. Devices tell the lock state of an account only to whoever
+    /// fails to log in with it, so other locked accounts go unseen.
+    fn set_banner(sink: &CbSink, aggregator: &Aggregator) {
+        let locks = aggregator.locks().map(|(name, until)| {
+            format!(
+                "{}: gusta's account locked after failed logins, unlocks at {}",
+                name,
+                until.format("%H:%M:%S")
+            )
+        });
+        let disks = aggregator.disks().into_iter().filter_map(|d| {
+            d.disk
+                .problem
+                .map(|p| format!("{}: HDD {} {}", d.device, d.disk.hdd.id, p))
+        });
+        let text = locks.chain(disks).collect::<Vec<String>>().join("\n");
+
+        let _res = sink.send(Box::new(move |s| {
+            s.call_on_name(view_names::BANNER, |t: &mut TextView| {
+                t.set_content(StyledString::styled(text, Color::Light(BaseColor::Red)));
+            });
+        }));
+    }
+
+    /// The sessions online, with the snapshots taken of them so far.
+    fn online(
+        aggregator: &Aggregator,
+        snapshot_links: &StdMutex<HashMap<u64, PathBuf>>,
+    ) -> Vec<DeviceUser> {
+        let links = snapshot_links.lock().unwrap();
+        let mut current = aggregator.online();
+        for user in current.iter_mut() {
+            user.snapshot = links.get(&user.hash_value()).cloned();
+        }
+
+        current
+    }
+
+    fn set_table<T, H>(sink: &CbSink, name: &'static str, items: Vec<T>)
+    where
+        T: TableViewItem<H> + PartialEq + Send + 'static,
+        H: Eq + Hash + Copy + Clone + Send + 'static,
+    {
+        let _res = sink.send(Box::new(move |s| {
+            s.call_on_name(name, |t: &mut TableView<T, H>| {
+                t.set_items_stable(items);
+            });
+        }));
+    }
+
+    /// Plays the alert sound, telling on the status line when it can't.
+    async fn ring(sb: &Mutex<SoundBank>, sink: &CbSink) {
+        if let Err(e) = sb.lock().await.play() {
+            Self::set_status(sink, format!("Alert sound failed: {:#}", e));
+        }
+    }
+
+    fn set_status(sink: &CbSink, text: String) {
+        let _res = sink.send(Box::new(move |s| {
+            s.call_on_name(view_names::STATUS, |t: &mut TextView| {
+                t.set_content(text);
+            });
+        }));
+    }
+
+    fn set_device_info(sink: &CbSink, aggregator: &Aggregator) {
+        let text = aggregator
+            .infos()
+            .map(|(name, info)| match info {
+                Some(i) => format!(
+                    "{:<12} {} ({})  SN {}  FW {} {}  MAC {}",
+                    name,
+                    i.device_name,
+                    i.model,
+                    i.serial_number,
+                    i.firmware_version,
+                    i.firmware_released_date,
+                    i.mac_address
+                ),
+                None => format!("{:<12} -", name),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let _res = sink.send(Box::new(move |s| {
+            s.call_on_name(view_names::DEVICE_INFO, |t: &mut TextView| {
+                t.set_content(text);
+            });
+        }));
+    }
+
     pub fn stop(&mut self) -> Result<()> {
         match &mut self.status {
-            Status::Idle => return Err(Error::msg("app not running")),
+            Status::Idle => Err(Error::msg("app not running")),
             Status::Running { cursive, fetch_jh } => {
                 cursive.quit();
                 fetch_jh.abort();
@@ -178,6 +614,29 @@ impl AppTui {
     }
 }
 
+/// Runs `on_pick` right away when there is a single device, asks otherwise.
+fn pick_device(
+    s: &mut Cursive,
+    title: &str,
+    devices: Vec<String>,
+    on_pick: impl Fn(&mut Cursive, String) + Send + Sync + 'static,
+) {
+    if let [device] = devices.as_slice() {
+        return on_pick(s, device.clone());
+    }
+
+    s.add_layer(
+        Dialog::around(SelectView::new().with_all_str(devices).on_submit(
+            move |s, device: &String| {
+                s.pop_layer();
+                on_pick(s, device.clone());
+            },
+        ))
+        .title(title)
+        .dismiss_button("Cancel"),
+    );
+}
+
 impl Drop for AppTui {
     fn drop(&mut self) {
         match &mut self.status {
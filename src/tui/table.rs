@@ -2,37 +2,53 @@ use cursive::{align::HAlign, view::Nameable, View};
 use cursive_table_view::{TableView, TableViewItem};
 use std::cmp::Ordering;
 
-use crate::client::OnlineUser;
+use crate::{
+    client::LogEntry,
+    monitor::{DeviceAlert, DeviceChannel, DeviceDisk, DeviceUser},
+};
 
+#[allow(dead_code)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum UserColumn {
+    Device,
     Id,
     Name,
     UserType,
     LoginTime,
     ClientAddress,
+    Snapshot,
 }
 
 impl UserColumn {
     pub fn as_str(&self) -> &str {
         match *self {
+            UserColumn::Device => "Device",
             UserColumn::Id => "Id",
             UserColumn::Name => "Name",
             UserColumn::UserType => "UserType",
             UserColumn::LoginTime => "Login",
             UserColumn::ClientAddress => "IP",
+            UserColumn::Snapshot => "Snapshot",
         }
     }
 }
 
-impl TableViewItem<UserColumn> for OnlineUser {
+impl TableViewItem<UserColumn> for DeviceUser {
     fn to_column(&self, column: UserColumn) -> String {
+        let user = &self.user;
         match column {
-            UserColumn::Id => self.id.to_string(),
-            UserColumn::Name => self.name.clone(),
-            UserColumn::UserType => self.user_type.clone(),
-            UserColumn::LoginTime => self.login_time.clone(),
-            UserColumn::ClientAddress => self.client_address.ip_address.clone(),
+            UserColumn::Device => self.device.clone(),
+            UserColumn::Id => user.id.to_string(),
+            UserColumn::Name => user.name.clone(),
+            UserColumn::UserType => user.user_type.clone(),
+            UserColumn::LoginTime => user.login_time.clone(),
+            UserColumn::ClientAddress => user.client_address.ip_address.clone(),
+            UserColumn::Snapshot => self
+                .snapshot
+                .as_ref()
+                .and_then(|s| s.file_name())
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
         }
     }
 
@@ -40,36 +56,345 @@ impl TableViewItem<UserColumn> for OnlineUser {
     where
         Self: Sized,
     {
+        let (user, other_user) = (&self.user, &other.user);
         match column {
-            UserColumn::Id => self.id.cmp(&other.id),
-            UserColumn::Name => self.name.cmp(&other.name),
-            UserColumn::UserType => self.user_type.cmp(&other.user_type),
-            UserColumn::LoginTime => self.login_time.cmp(&other.login_time),
-            UserColumn::ClientAddress => self
+            UserColumn::Device => self.device.cmp(&other.device),
+            UserColumn::Id => user.id.cmp(&other_user.id),
+            UserColumn::Name => user.name.cmp(&other_user.name),
+            UserColumn::UserType => user.user_type.cmp(&other_user.user_type),
+            UserColumn::LoginTime => user.login_time.cmp(&other_user.login_time),
+            UserColumn::ClientAddress => user
                 .client_address
                 .ip_address
-                .cmp(&other.client_address.ip_address),
+                .cmp(&other_user.client_address.ip_address),
+            UserColumn::Snapshot => self.snapshot.cmp(&other.snapshot),
         }
     }
 }
 
 pub fn build_table(name: &str) -> impl View {
-    let mut table = TableView::<OnlineUser, UserColumn>::new()
+    let mut table = TableView::<DeviceUser, UserColumn>::new()
         // .column(UserColumn::Id, UserColumn::Id.as_str(), |c| {c})
         // .column(UserColumn::UserType, UserColumn::UserType.as_str(), |c| {c})
+        .column(UserColumn::Device, UserColumn::Device.as_str(), |c| {
+            c.align(HAlign::Left).width_percent(15)
+        })
         .column(UserColumn::Name, UserColumn::Name.as_str(), |c| {
-            c.align(HAlign::Center).width_percent(20)
+            c.align(HAlign::Center).width_percent(15)
         })
         .column(
             UserColumn::ClientAddress,
             UserColumn::ClientAddress.as_str(),
-            |c| c.align(HAlign::Right).width_percent(40),
+            |c| c.align(HAlign::Right).width_percent(20),
         )
         .column(UserColumn::LoginTime, UserColumn::LoginTime.as_str(), |c| {
             c.align(HAlign::Center)
                 .align(HAlign::Right)
-                .width_percent(40)
+                .width_percent(20)
+        })
+        .column(UserColumn::Snapshot, UserColumn::Snapshot.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(30)
         });
     table.sort_by(UserColumn::Name, Ordering::Greater);
     table.with_name(name)
 }
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AlertColumn {
+    Device,
+    Time,
+    Kind,
+    Channel,
+    Description,
+}
+
+impl AlertColumn {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            AlertColumn::Device => "Device",
+            AlertColumn::Time => "Time",
+            AlertColumn::Kind => "Event",
+            AlertColumn::Channel => "Channel",
+            AlertColumn::Description => "Description",
+        }
+    }
+}
+
+impl TableViewItem<AlertColumn> for DeviceAlert {
+    fn to_column(&self, column: AlertColumn) -> String {
+        let alert = &self.alert;
+        match column {
+            AlertColumn::Device => self.device.clone(),
+            AlertColumn::Time => alert.date_time.clone(),
+            AlertColumn::Kind => alert.kind().to_string(),
+            AlertColumn::Channel => alert.channel_id.map(|c| c.to_string()).unwrap_or_default(),
+            AlertColumn::Description => alert.event_description.clone(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: AlertColumn) -> std::cmp::Ordering
+    where
+        Self: Sized,
+    {
+        let (alert, other_alert) = (&self.alert, &other.alert);
+        match column {
+            AlertColumn::Device => self.device.cmp(&other.device),
+            AlertColumn::Time => alert.date_time.cmp(&other_alert.date_time),
+            AlertColumn::Kind => alert.event_type.cmp(&other_alert.event_type),
+            AlertColumn::Channel => alert.channel_id.cmp(&other_alert.channel_id),
+            AlertColumn::Description => alert.event_description.cmp(&other_alert.event_description),
+        }
+    }
+}
+
+pub fn build_alert_table(name: &str) -> impl View {
+    let mut table = TableView::<DeviceAlert, AlertColumn>::new()
+        .column(AlertColumn::Device, AlertColumn::Device.as_str(), |c| {
+            c.align(HAlign::Left).width_percent(20)
+        })
+        .column(AlertColumn::Time, AlertColumn::Time.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(25)
+        })
+        .column(AlertColumn::Kind, AlertColumn::Kind.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(15)
+        })
+        .column(AlertColumn::Channel, AlertColumn::Channel.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(10)
+        })
+        .column(
+            AlertColumn::Description,
+            AlertColumn::Description.as_str(),
+            |c| c.align(HAlign::Right).width_percent(30),
+        );
+    table.sort_by(AlertColumn::Time, Ordering::Greater);
+    table.with_name(name)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LogColumn {
+    Time,
+    Kind,
+    User,
+    ClientAddress,
+}
+
+impl LogColumn {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            LogColumn::Time => "Time",
+            LogColumn::Kind => "Event",
+            LogColumn::User => "User",
+            LogColumn::ClientAddress => "IP",
+        }
+    }
+}
+
+impl TableViewItem<LogColumn> for LogEntry {
+    fn to_column(&self, column: LogColumn) -> String {
+        match column {
+            LogColumn::Time => self.time.clone(),
+            LogColumn::Kind => self.minor_type().to_string(),
+            LogColumn::User => self.user_name.clone(),
+            LogColumn::ClientAddress => self.ip_address.clone(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: LogColumn) -> std::cmp::Ordering
+    where
+        Self: Sized,
+    {
+        match column {
+            LogColumn::Time => self.time.cmp(&other.time),
+            LogColumn::Kind => self.minor_type().cmp(other.minor_type()),
+            LogColumn::User => self.user_name.cmp(&other.user_name),
+            LogColumn::ClientAddress => self.ip_address.cmp(&other.ip_address),
+        }
+    }
+}
+
+pub fn build_log_table(entries: Vec<LogEntry>) -> impl View {
+    let mut table = TableView::<LogEntry, LogColumn>::new()
+        .column(LogColumn::Time, LogColumn::Time.as_str(), |c| {
+            c.align(HAlign::Left).width_percent(30)
+        })
+        .column(LogColumn::Kind, LogColumn::Kind.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(20)
+        })
+        .column(LogColumn::User, LogColumn::User.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(20)
+        })
+        .column(
+            LogColumn::ClientAddress,
+            LogColumn::ClientAddress.as_str(),
+            |c| c.align(HAlign::Right).width_percent(30),
+        );
+    table.set_items(entries);
+    table.sort_by(LogColumn::Time, Ordering::Greater);
+    table
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChannelColumn {
+    Device,
+    Id,
+    State,
+    ClientAddress,
+    Resolution,
+}
+
+impl ChannelColumn {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            ChannelColumn::Device => "Device",
+            ChannelColumn::Id => "Ch",
+            ChannelColumn::State => "State",
+            ChannelColumn::ClientAddress => "IP",
+            ChannelColumn::Resolution => "Resolution",
+        }
+    }
+}
+
+impl TableViewItem<ChannelColumn> for DeviceChannel {
+    fn to_column(&self, column: ChannelColumn) -> String {
+        let channel = &self.channel;
+        match column {
+            ChannelColumn::Device => self.device.clone(),
+            ChannelColumn::Id => channel.id.to_string(),
+            ChannelColumn::State if channel.online => "online".into(),
+            ChannelColumn::State => format!("offline ({})", channel.detect_result),
+            ChannelColumn::ClientAddress => channel.source.ip_address.clone(),
+            ChannelColumn::Resolution => channel.resolution.clone(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: ChannelColumn) -> std::cmp::Ordering
+    where
+        Self: Sized,
+    {
+        let (channel, other_channel) = (&self.channel, &other.channel);
+        match column {
+            ChannelColumn::Device => self
+                .device
+                .cmp(&other.device)
+                .then(channel.id.cmp(&other_channel.id)),
+            ChannelColumn::Id => channel.id.cmp(&other_channel.id),
+            ChannelColumn::State => channel.online.cmp(&other_channel.online),
+            ChannelColumn::ClientAddress => channel
+                .source
+                .ip_address
+                .cmp(&other_channel.source.ip_address),
+            ChannelColumn::Resolution => channel.resolution.cmp(&other_channel.resolution),
+        }
+    }
+}
+
+pub fn build_channel_table(name: &str) -> impl View {
+    let mut table = TableView::<DeviceChannel, ChannelColumn>::new()
+        .column(ChannelColumn::Device, ChannelColumn::Device.as_str(), |c| {
+            c.align(HAlign::Left).width_percent(20)
+        })
+        .column(ChannelColumn::Id, ChannelColumn::Id.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(10)
+        })
+        .column(ChannelColumn::State, ChannelColumn::State.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(25)
+        })
+        .column(
+            ChannelColumn::ClientAddress,
+            ChannelColumn::ClientAddress.as_str(),
+            |c| c.align(HAlign::Center).width_percent(25),
+        )
+        .column(
+            ChannelColumn::Resolution,
+            ChannelColumn::Resolution.as_str(),
+            |c| c.align(HAlign::Right).width_percent(20),
+        );
+    table.sort_by(ChannelColumn::Device, Ordering::Less);
+    table.with_name(name)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DiskColumn {
+    Device,
+    Id,
+    Status,
+    Capacity,
+    Free,
+    Property,
+}
+
+impl DiskColumn {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            DiskColumn::Device => "Device",
+            DiskColumn::Id => "HDD",
+            DiskColumn::Status => "Status",
+            DiskColumn::Capacity => "Capacity",
+            DiskColumn::Free => "Free",
+            DiskColumn::Property => "Property",
+        }
+    }
+}
+
+impl TableViewItem<DiskColumn> for DeviceDisk {
+    fn to_column(&self, column: DiskColumn) -> String {
+        let hdd = &self.disk.hdd;
+        match column {
+            DiskColumn::Device => self.device.clone(),
+            DiskColumn::Id => hdd.id.to_string(),
+            DiskColumn::Status => match &self.disk.problem {
+                Some(problem) => format!("{} !", problem),
+                None => hdd.status.clone(),
+            },
+            DiskColumn::Capacity => format!("{:.1} GB", hdd.capacity as f64 / 1024.0),
+            DiskColumn::Free => format!(
+                "{:.1} GB ({}%)",
+                hdd.free_space as f64 / 1024.0,
+                hdd.free_percent()
+            ),
+            DiskColumn::Property => hdd.property.clone(),
+        }
+    }
+
+    fn cmp(&self, other: &Self, column: DiskColumn) -> std::cmp::Ordering
+    where
+        Self: Sized,
+    {
+        let (hdd, other_hdd) = (&self.disk.hdd, &other.disk.hdd);
+        match column {
+            DiskColumn::Device => self
+                .device
+                .cmp(&other.device)
+                .then(hdd.id.cmp(&other_hdd.id)),
+            DiskColumn::Id => hdd.id.cmp(&other_hdd.id),
+            DiskColumn::Status => hdd.status.cmp(&other_hdd.status),
+            DiskColumn::Capacity => hdd.capacity.cmp(&other_hdd.capacity),
+            DiskColumn::Free => hdd.free_percent().cmp(&other_hdd.free_percent()),
+            DiskColumn::Property => hdd.property.cmp(&other_hdd.property),
+        }
+    }
+}
+
+pub fn build_disk_table(name: &str) -> impl View {
+    let mut table = TableView::<DeviceDisk, DiskColumn>::new()
+        .column(DiskColumn::Device, DiskColumn::Device.as_str(), |c| {
+            c.align(HAlign::Left).width_percent(20)
+        })
+        .column(DiskColumn::Id, DiskColumn::Id.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(10)
+        })
+        .column(DiskColumn::Status, DiskColumn::Status.as_str(), |c| {
+            c.align(HAlign::Center).width_percent(20)
+        })
+        .column(DiskColumn::Capacity, DiskColumn::Capacity.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(15)
+        })
+        .column(DiskColumn::Free, DiskColumn::Free.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(20)
+        })
+        .column(DiskColumn::Property, DiskColumn::Property.as_str(), |c| {
+            c.align(HAlign::Right).width_percent(15)
+        });
+    table.sort_by(DiskColumn::Device, Ordering::Less);
+    table.with_name(name)
+}
@@ -2,6 +2,25 @@ use cursive::{
     theme::{BorderStyle, Palette, Theme},
     With,
 };
+use serde::Deserialize;
+
+/// The `theme` of the config.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeName {
+    pub fn theme(&self) -> Theme {
+        match self {
+            ThemeName::Dark => dark(),
+            ThemeName::Light => light(),
+        }
+    }
+}
 
 pub fn dark() -> Theme {
     Theme {
@@ -33,3 +52,30 @@ pub fn dark() -> Theme {
         }),
     }
 }
+
+pub fn light() -> Theme {
+    Theme {
+        shadow: false,
+        borders: BorderStyle::Simple,
+        palette: Palette::retro().with(|palette| {
+            use cursive::theme::BaseColor::*;
+            {
+                use cursive::theme::PaletteColor::*;
+
+                palette[Background] = White.light();
+                palette[View] = White.light();
+                palette[Primary] = Black.dark();
+                palette[TitlePrimary] = Blue.dark();
+                palette[Secondary] = Blue.dark();
+                palette[Highlight] = Blue.light();
+            }
+
+            {
+                use cursive::theme::Effect::*;
+                use cursive::theme::PaletteStyle::*;
+                use cursive::theme::Style;
+                palette[Highlight] = Style::from(Blue.dark()).combine(Bold);
+            }
+        }),
+    }
+}